@@ -0,0 +1,228 @@
+use itertools::Itertools;
+use keyvalues_parser::{Obj, Vdf};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str,
+};
+
+use super::depot::{AppDepots, DepotTarget};
+use crate::{error::Error, utils::vdf};
+
+#[derive(Debug, Default)]
+pub struct AppIcons {
+    pub icon: Option<String>,
+    pub logo: Option<String>,
+    pub logo_small: Option<String>,
+    pub client_icon: Option<String>,
+    pub client_tga: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct AppCommon {
+    pub name: Option<String>,
+    pub r#type: Option<String>,
+    pub oslist: Vec<String>,
+    pub release_state: Option<String>,
+    pub parent: Option<u32>,
+    pub icons: AppIcons,
+}
+
+impl AppCommon {
+    fn vdf_parse(&mut self, common: &Obj<'_>) {
+        self.name = vdf::get_str(common, "name").map(str::to_string);
+        self.r#type = vdf::get_str(common, "type").map(str::to_lowercase);
+        self.oslist = vdf::get_list(common, "oslist");
+        self.release_state = vdf::get_str(common, "releasestate").map(str::to_string);
+        self.parent = vdf::get_str(common, "parent").and_then(|id| id.parse().ok());
+        self.icons = AppIcons {
+            icon: vdf::get_str(common, "icon").map(str::to_string),
+            logo: vdf::get_str(common, "logo").map(str::to_string),
+            logo_small: vdf::get_str(common, "logo_small").map(str::to_string),
+            client_icon: vdf::get_str(common, "clienticon").map(str::to_string),
+            client_tga: vdf::get_str(common, "clienttga").map(str::to_string),
+        };
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct LaunchEntry {
+    pub executable: String,
+    pub arguments: Option<String>,
+    pub working_dir: Option<String>,
+    pub r#type: Option<String>,
+    pub oslist: Vec<String>,
+    pub osarch: Option<String>,
+    pub beta_key: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedLaunch {
+    pub executable: PathBuf,
+    pub arguments: Option<String>,
+    pub working_dir: PathBuf,
+}
+
+impl LaunchEntry {
+    fn vdf_parse(entry: &Obj<'_>) -> Self {
+        let config = vdf::get_obj(entry, "config");
+        let config_str = |key: &str| {
+            config
+                .and_then(|config| vdf::get_str(config, key))
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+        };
+        Self {
+            executable: vdf::get_str(entry, "executable")
+                .unwrap_or_default()
+                .to_string(),
+            arguments: vdf::get_str(entry, "arguments").map(str::to_string),
+            working_dir: vdf::get_str(entry, "workingdir")
+                .filter(|dir| !dir.is_empty())
+                .map(str::to_string),
+            r#type: vdf::get_str(entry, "type").map(str::to_lowercase),
+            oslist: config
+                .map(|config| vdf::get_list(config, "oslist"))
+                .unwrap_or_default(),
+            osarch: config_str("osarch"),
+            beta_key: config_str("betakey"),
+        }
+    }
+
+    pub fn matches(&self, target: &DepotTarget) -> bool {
+        if !self.oslist.is_empty() && !self.oslist.contains(&target.os) {
+            return false;
+        }
+        if let (Some(osarch), Some(arch)) = (&self.osarch, &target.arch) {
+            if osarch != arch {
+                return false;
+            }
+        }
+        self.beta_key
+            .as_ref()
+            .is_none_or(|beta_key| *beta_key == target.branch)
+    }
+
+    /// Joins the executable and working directory onto `install_dir`, the working
+    /// directory defaults to the one holding the executable.
+    pub fn resolve<P: AsRef<Path>>(&self, install_dir: P) -> ResolvedLaunch {
+        let install_dir = install_dir.as_ref();
+        let executable = install_dir.join(normalize_path(&self.executable));
+        let working_dir = match &self.working_dir {
+            Some(dir) => install_dir.join(normalize_path(dir)),
+            None => executable
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_else(|| install_dir.to_path_buf()),
+        };
+        ResolvedLaunch {
+            executable,
+            arguments: self.arguments.clone(),
+            working_dir,
+        }
+    }
+}
+
+fn normalize_path(path: &str) -> PathBuf {
+    path.split(['\\', '/'])
+        .filter(|part| !part.is_empty() && *part != ".")
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct AppConfig {
+    pub install_dir: Option<String>,
+    pub launch: Vec<LaunchEntry>,
+}
+
+impl AppConfig {
+    fn vdf_parse(&mut self, config: &Obj<'_>) {
+        self.install_dir = vdf::get_str(config, "installdir").map(str::to_string);
+        if let Some(launch) = vdf::get_obj(config, "launch") {
+            // entries are keyed by index, which the BTreeMap sorts as strings
+            self.launch = launch
+                .iter()
+                .filter_map(|(key, value)| Some((key.parse::<u32>().ok()?, value.first()?)))
+                .sorted_by_key(|(index, _)| *index)
+                .filter_map(|(_, value)| value.get_obj())
+                .map(LaunchEntry::vdf_parse)
+                .collect();
+        }
+    }
+
+    /// Picks the launch entry Steam would default to for `target`, preferring
+    /// entries bound to the target branch and then `default` typed ones.
+    pub fn launch_for(&self, target: &DepotTarget) -> Option<&LaunchEntry> {
+        self.launch
+            .iter()
+            .filter(|entry| entry.matches(target))
+            .min_by_key(|entry| {
+                (
+                    entry.beta_key.is_none(),
+                    !matches!(entry.r#type.as_deref(), None | Some("default")),
+                )
+            })
+    }
+}
+
+#[derive(Debug)]
+pub struct AppInfo {
+    pub app_id: u32,
+    pub common: AppCommon,
+    pub extended: BTreeMap<String, String>,
+    pub config: AppConfig,
+    pub depots: AppDepots,
+    raw: String,
+}
+
+impl AppInfo {
+    pub fn new(app_id: u32) -> Self {
+        Self {
+            app_id,
+            common: AppCommon::default(),
+            extended: BTreeMap::new(),
+            config: AppConfig::default(),
+            depots: AppDepots::new(app_id),
+            raw: String::new(),
+        }
+    }
+
+    /// Re-parses the original appinfo text for anything not covered by the model.
+    pub fn vdf(&self) -> Result<Vdf<'_>, Error> {
+        Ok(Vdf::parse(&self.raw)?)
+    }
+
+    pub fn vdf_parse(&mut self, buffer: &[u8]) -> Result<(), Error> {
+        let raw = str::from_utf8(buffer)
+            .map_err(|err| Error::InvalidVDF(err.to_string()))?
+            .trim_end_matches('\0');
+        let kv = Vdf::parse(raw)?;
+        let appinfo = kv
+            .value
+            .get_obj()
+            .ok_or(Error::Unexpected("failed to get appinfo value".to_string()))?;
+
+        if let Some(common) = vdf::get_obj(appinfo, "common") {
+            self.common.vdf_parse(common);
+        }
+        if let Some(extended) = vdf::get_obj(appinfo, "extended") {
+            self.extended = extended
+                .iter()
+                .filter_map(|(key, value)| {
+                    Some((key.to_string(), value.first()?.get_str()?.to_string()))
+                })
+                .collect();
+        }
+        if let Some(config) = vdf::get_obj(appinfo, "config") {
+            self.config.vdf_parse(config);
+        }
+
+        self.depots.app_name = self.common.name.clone();
+        if let Some(depots) = appinfo.get("depots") {
+            self.depots.depots_parse(depots)?;
+        }
+
+        self.raw = raw.to_string();
+        Ok(())
+    }
+}
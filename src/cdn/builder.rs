@@ -0,0 +1,278 @@
+use futures::stream::BoxStream;
+use reqwest::Client;
+use std::{
+    fmt::{self, Debug},
+    future::Future,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tokio::time::sleep;
+
+use crate::Error;
+
+use super::{
+    connection::SteamConnection, http::HttpOptions, inner::InnerClient, license::License,
+    server_source::ServerSource, CDNClient, MANIFEST_VERSION,
+};
+
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How failed content requests are repeated, each wait doubling the previous one up to a minute.
+#[derive(Debug, Clone)]
+pub struct RetryPolicy {
+    pub max_attempts: u32,
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 3,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::ZERO,
+        }
+    }
+
+    /// Doubles per attempt, capped at `MAX_BACKOFF` instead of overflowing.
+    fn backoff_for(&self, attempt: u32) -> Duration {
+        2u32.checked_pow(attempt - 1)
+            .and_then(|factor| self.backoff.checked_mul(factor))
+            .map_or(MAX_BACKOFF, |backoff| backoff.min(MAX_BACKOFF))
+    }
+
+    pub(crate) async fn run<T, F, Fut>(&self, mut request: F) -> Result<T, Error>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, Error>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match request().await {
+                Err(err) if err.is_retryable() && attempt < self.max_attempts => {
+                    sleep(self.backoff_for(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Checks applied to downloaded content on top of the ones built into its containers.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Verification {
+    /// Only what decompression checks by itself.
+    Lenient,
+    /// Chunks must match the size and checksum listed in the manifest.
+    #[default]
+    Strict,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct ClientConfig {
+    pub cell_id: Option<u32>,
+    pub concurrency: usize,
+    pub adaptive_concurrency: Option<(usize, usize)>,
+    pub retry: RetryPolicy,
+    pub cache_dir: Option<PathBuf>,
+    pub verification: Verification,
+    pub manifest_version: usize,
+    pub spread_servers: usize,
+    pub connections_per_server: usize,
+    pub read_timeout: Option<Duration>,
+    pub server_list_ttl: Duration,
+}
+
+impl Default for ClientConfig {
+    fn default() -> Self {
+        Self {
+            cell_id: None,
+            concurrency: 4,
+            adaptive_concurrency: None,
+            retry: RetryPolicy::default(),
+            cache_dir: None,
+            verification: Verification::default(),
+            manifest_version: MANIFEST_VERSION,
+            spread_servers: 3,
+            connections_per_server: 8,
+            read_timeout: None,
+            server_list_ttl: Duration::from_secs(60 * 60),
+        }
+    }
+}
+
+pub struct CDNClientBuilder {
+    connection: Arc<dyn SteamConnection>,
+    http_client: Option<Client>,
+    http_options: HttpOptions,
+    server_source: Option<Arc<dyn ServerSource>>,
+    licenses: Option<Vec<License>>,
+    license_lists: Option<BoxStream<'static, Vec<License>>>,
+    config: ClientConfig,
+}
+
+impl Debug for CDNClientBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CDNClientBuilder")
+            .field("connection", &self.connection)
+            .field("http_client", &self.http_client)
+            .field("http_options", &self.http_options)
+            .field("server_source", &self.server_source)
+            .field("licenses", &self.licenses)
+            .field("license_lists", &self.license_lists.is_some())
+            .field("config", &self.config)
+            .finish()
+    }
+}
+
+impl CDNClientBuilder {
+    pub fn new(connection: Arc<dyn SteamConnection>) -> Self {
+        Self {
+            connection,
+            http_client: None,
+            http_options: HttpOptions::default(),
+            server_source: None,
+            licenses: None,
+            license_lists: None,
+            config: ClientConfig::default(),
+        }
+    }
+
+    /// Uses `client` as is, `http_options` then only contribute the read timeout.
+    pub fn http_client(mut self, client: Client) -> Self {
+        self.http_client = Some(client);
+        self
+    }
+
+    pub fn http_options(mut self, options: HttpOptions) -> Self {
+        self.http_options = options;
+        self
+    }
+
+    /// Cell to pick servers for instead of the one the CM assigned.
+    pub fn cell_id(mut self, cell_id: u32) -> Self {
+        self.config.cell_id = Some(cell_id);
+        self
+    }
+
+    /// Chunks downloaded at once per file when the caller doesn't say otherwise.
+    pub fn concurrency(mut self, concurrency: usize) -> Self {
+        self.config.concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Lets chunk concurrency float between `min` and `max` based on throughput,
+    /// errors and latency, starting from `concurrency`. The limit is shared by
+    /// all downloads of the client that don't pass their own.
+    pub fn adaptive_concurrency(mut self, min: usize, max: usize) -> Self {
+        self.config.adaptive_concurrency = Some((min, max));
+        self
+    }
+
+    pub fn retry_policy(mut self, retry: RetryPolicy) -> Self {
+        self.config.retry = retry;
+        self
+    }
+
+    /// Defaults to the web api, using the configured http client.
+    pub fn server_source<S: ServerSource + 'static>(mut self, source: S) -> Self {
+        self.server_source = Some(Arc::new(source));
+        self
+    }
+
+    /// Directory where downloaded manifests and the server list are kept and reused from.
+    pub fn cache_dir<P: Into<PathBuf>>(mut self, cache_dir: P) -> Self {
+        self.config.cache_dir = Some(cache_dir.into());
+        self
+    }
+
+    pub fn verification(mut self, verification: Verification) -> Self {
+        self.config.verification = verification;
+        self
+    }
+
+    pub fn manifest_version(mut self, version: usize) -> Self {
+        self.config.manifest_version = version;
+        self
+    }
+
+    /// Requests are spread over the `servers` best scored ones, with at most
+    /// `connections` in flight to each.
+    pub fn server_spread(mut self, servers: usize, connections: usize) -> Self {
+        self.config.spread_servers = servers.max(1);
+        self.config.connections_per_server = connections.max(1);
+        self
+    }
+
+    /// How long a server list is used before it's fetched again, persisted ones included.
+    pub fn server_list_ttl(mut self, ttl: Duration) -> Self {
+        self.config.server_list_ttl = ttl;
+        self
+    }
+
+    /// Steam pushes the license list once right after logon, and a client built later
+    /// never sees it. Supplies a list obtained some other way instead, later pushes
+    /// still replace it.
+    pub fn licenses(mut self, licenses: Vec<License>) -> Self {
+        self.licenses = Some(licenses);
+        self
+    }
+
+    /// Listens to `license_lists` instead of subscribing through the connection on
+    /// build, e.g. a subscription made before logon so the first push isn't missed.
+    pub fn license_lists(mut self, license_lists: BoxStream<'static, Vec<License>>) -> Self {
+        self.license_lists = Some(license_lists);
+        self
+    }
+
+    pub async fn build(mut self) -> Result<CDNClient, Error> {
+        let web_client = match self.http_client {
+            Some(client) => client,
+            None => self.http_options.build()?,
+        };
+        self.config.read_timeout = self.http_options.read_timeout;
+        let license_lists = self
+            .license_lists
+            .unwrap_or_else(|| self.connection.license_lists());
+        let inner = Arc::new(InnerClient::new(
+            self.connection,
+            web_client,
+            self.server_source,
+            (self.licenses, license_lists),
+            self.config,
+        ));
+        InnerClient::spawn_server_maintenance(&inner);
+        Ok(CDNClient { inner })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap() {
+        let retry = RetryPolicy {
+            max_attempts: u32::MAX,
+            backoff: Duration::from_millis(500),
+        };
+        assert_eq!(retry.backoff_for(1), Duration::from_millis(500));
+        assert_eq!(retry.backoff_for(3), Duration::from_secs(2));
+        assert_eq!(retry.backoff_for(40), MAX_BACKOFF);
+
+        let retry = RetryPolicy {
+            max_attempts: u32::MAX,
+            backoff: Duration::MAX,
+        };
+        assert_eq!(retry.backoff_for(2), MAX_BACKOFF);
+    }
+}
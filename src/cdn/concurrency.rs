@@ -0,0 +1,133 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use tokio::sync::Notify;
+
+// throughput is compared over windows this long, and the limit drops at most once per window
+const WINDOW: Duration = Duration::from_secs(1);
+const GROWTH_THRESHOLD: f64 = 1.05;
+const LATENCY_SPIKE_FACTOR: f64 = 3.0;
+const LATENCY_WEIGHT: f64 = 0.2;
+
+/// AIMD limit on chunk downloads shared by every download of a client: one more
+/// slot while throughput keeps growing, half of them gone on errors or latency spikes.
+#[derive(Debug)]
+pub(crate) struct AdaptiveLimit {
+    min: usize,
+    max: usize,
+    state: Mutex<State>,
+    released: Notify,
+}
+
+#[derive(Debug)]
+struct State {
+    limit: usize,
+    in_flight: usize,
+    window_bytes: usize,
+    window_started: Instant,
+    throughput: Option<f64>,
+    latency_ms: Option<f64>,
+    decreased: Option<Instant>,
+}
+
+pub(crate) struct AdaptivePermit<'a> {
+    limit: &'a AdaptiveLimit,
+}
+
+impl Drop for AdaptivePermit<'_> {
+    fn drop(&mut self) {
+        self.limit.state.lock().unwrap().in_flight -= 1;
+        self.limit.released.notify_waiters();
+    }
+}
+
+impl AdaptiveLimit {
+    pub fn new(initial: usize, min: usize, max: usize) -> Self {
+        let min = min.max(1);
+        let max = max.max(min);
+        Self {
+            min,
+            max,
+            state: Mutex::new(State {
+                limit: initial.clamp(min, max),
+                in_flight: 0,
+                window_bytes: 0,
+                window_started: Instant::now(),
+                throughput: None,
+                latency_ms: None,
+                decreased: None,
+            }),
+            released: Notify::new(),
+        }
+    }
+
+    pub fn limit(&self) -> usize {
+        self.state.lock().unwrap().limit
+    }
+
+    pub async fn acquire(&self) -> AdaptivePermit<'_> {
+        loop {
+            // registered before checking so a release in between isn't missed
+            let released = self.released.notified();
+            {
+                let mut state = self.state.lock().unwrap();
+                if state.in_flight < state.limit {
+                    state.in_flight += 1;
+                    return AdaptivePermit { limit: self };
+                }
+            }
+            released.await;
+        }
+    }
+
+    pub fn record_success(&self, bytes: usize, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let latency_ms = latency.as_secs_f64() * 1000.0;
+        match state.latency_ms {
+            Some(average) => {
+                if latency_ms > average * LATENCY_SPIKE_FACTOR {
+                    self.decrease(&mut state);
+                }
+                // spikes count too, so a lasting change e.g. in chunk size becomes the new normal
+                state.latency_ms = Some(average + LATENCY_WEIGHT * (latency_ms - average));
+            }
+            None => state.latency_ms = Some(latency_ms),
+        }
+
+        state.window_bytes += bytes;
+        let elapsed = state.window_started.elapsed();
+        if elapsed < WINDOW {
+            return;
+        }
+        let throughput = state.window_bytes as f64 / elapsed.as_secs_f64();
+        if state
+            .throughput
+            .is_none_or(|previous| throughput > previous * GROWTH_THRESHOLD)
+            && state.limit < self.max
+        {
+            state.limit += 1;
+            self.released.notify_waiters();
+        }
+        state.throughput = Some(throughput);
+        state.window_bytes = 0;
+        state.window_started = Instant::now();
+    }
+
+    pub fn record_failure(&self) {
+        self.decrease(&mut self.state.lock().unwrap());
+    }
+
+    fn decrease(&self, state: &mut State) {
+        if state
+            .decreased
+            .is_some_and(|decreased| decreased.elapsed() < WINDOW)
+        {
+            return;
+        }
+        state.limit = (state.limit / 2).max(self.min);
+        state.decreased = Some(Instant::now());
+        // throughput at the old limit says nothing about the new one
+        state.throughput = None;
+    }
+}
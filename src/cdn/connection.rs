@@ -0,0 +1,313 @@
+use futures::{
+    future::BoxFuture,
+    stream::{BoxStream, StreamExt},
+};
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    pin::pin,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use steam_vent::{
+    proto::{
+        steammessages_clientserver::CMsgClientLicenseList,
+        steammessages_clientserver_2::{
+            CMsgClientGetDepotDecryptionKey, CMsgClientGetDepotDecryptionKeyResponse,
+            CMsgClientRequestFreeLicense, CMsgClientRequestFreeLicenseResponse,
+        },
+        steammessages_clientserver_appinfo::{
+            cmsg_client_picsproduct_info_request::{AppInfo, PackageInfo},
+            CMsgClientPICSAccessTokenRequest, CMsgClientPICSAccessTokenResponse,
+            CMsgClientPICSProductInfoRequest, CMsgClientPICSProductInfoResponse,
+        },
+        steammessages_contentsystem_steamclient::{
+            CContentServerDirectory_GetCDNAuthToken_Request,
+            CContentServerDirectory_GetManifestRequestCode_Request,
+            CContentServerDirectory_GetServersForSteamPipe_Request,
+        },
+    },
+    Connection, ConnectionTrait, EResult,
+};
+
+use crate::{web_api::content_service::CDNServer, Error};
+
+use super::{
+    depot_key::DepotKey,
+    license::{GrantedLicenses, License},
+};
+
+/// PICS access tokens by app and package id.
+#[derive(Debug, Clone, Default)]
+pub struct AccessTokens {
+    pub apps: BTreeMap<u32, u64>,
+    pub packages: BTreeMap<u32, u64>,
+}
+
+/// Raw PICS buffers, text KeyValues for apps and binary ones for packages.
+#[derive(Debug, Clone, Default)]
+pub struct ProductInfo {
+    pub apps: BTreeMap<u32, Vec<u8>>,
+    pub packages: BTreeMap<u32, Vec<u8>>,
+    pub unknown_app_ids: Vec<u32>,
+    pub unknown_package_ids: Vec<u32>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CDNAuthToken {
+    pub token: String,
+    pub expiry: SystemTime,
+}
+
+/// Everything `CDNClient` asks of a logged on Steam connection, implemented by
+/// steam-vent's `Connection` and, with the `test-support` feature, by `FakeConnection`.
+pub trait SteamConnection: Debug + Send + Sync {
+    fn cell_id(&self) -> u32;
+
+    /// License lists as steam pushes them, after logon and whenever they change.
+    fn license_lists(&self) -> BoxStream<'static, Vec<License>>;
+
+    fn access_tokens(
+        &self,
+        app_ids: Vec<u32>,
+        package_ids: Vec<u32>,
+    ) -> BoxFuture<'_, Result<AccessTokens, Error>>;
+
+    fn product_info(&self, tokens: AccessTokens) -> BoxFuture<'_, Result<ProductInfo, Error>>;
+
+    fn depot_key(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+    ) -> BoxFuture<'_, Result<Option<DepotKey>, Error>>;
+
+    fn manifest_request_code(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+        manifest_id: u64,
+        branch: Option<String>,
+        branch_password_hash: Option<String>,
+    ) -> BoxFuture<'_, Result<u64, Error>>;
+
+    fn cdn_auth_token(
+        &self,
+        depot_id: u32,
+        host_name: String,
+    ) -> BoxFuture<'_, Result<Option<CDNAuthToken>, Error>>;
+
+    fn request_free_license(
+        &self,
+        app_ids: Vec<u32>,
+    ) -> BoxFuture<'_, Result<GrantedLicenses, Error>>;
+
+    fn servers(&self, cell_id: u32) -> BoxFuture<'_, Result<Vec<CDNServer>, Error>>;
+}
+
+impl SteamConnection for Connection {
+    fn cell_id(&self) -> u32 {
+        Connection::cell_id(self)
+    }
+
+    fn license_lists(&self) -> BoxStream<'static, Vec<License>> {
+        self.on::<CMsgClientLicenseList>()
+            .filter_map(|license_list| async move {
+                Some(
+                    license_list
+                        .ok()?
+                        .licenses
+                        .into_iter()
+                        .map(License::from)
+                        .collect(),
+                )
+            })
+            .boxed()
+    }
+
+    fn access_tokens(
+        &self,
+        app_ids: Vec<u32>,
+        package_ids: Vec<u32>,
+    ) -> BoxFuture<'_, Result<AccessTokens, Error>> {
+        Box::pin(async move {
+            let response: CMsgClientPICSAccessTokenResponse = self
+                .job(CMsgClientPICSAccessTokenRequest {
+                    appids: app_ids,
+                    packageids: package_ids,
+                    ..Default::default()
+                })
+                .await?;
+            Ok(AccessTokens {
+                apps: response
+                    .app_access_tokens
+                    .into_iter()
+                    .map(|token| (token.appid(), token.access_token()))
+                    .collect(),
+                packages: response
+                    .package_access_tokens
+                    .into_iter()
+                    .map(|token| (token.packageid(), token.access_token()))
+                    .collect(),
+            })
+        })
+    }
+
+    fn product_info(&self, tokens: AccessTokens) -> BoxFuture<'_, Result<ProductInfo, Error>> {
+        Box::pin(async move {
+            let request = CMsgClientPICSProductInfoRequest {
+                apps: tokens
+                    .apps
+                    .into_iter()
+                    .map(|(appid, access_token)| AppInfo {
+                        appid: Some(appid),
+                        access_token: Some(access_token),
+                        ..Default::default()
+                    })
+                    .collect(),
+                packages: tokens
+                    .packages
+                    .into_iter()
+                    .map(|(packageid, access_token)| PackageInfo {
+                        packageid: Some(packageid),
+                        access_token: Some(access_token),
+                        ..Default::default()
+                    })
+                    .collect(),
+                meta_data_only: Some(false),
+                ..Default::default()
+            };
+            let mut responses =
+                pin!(self.job_multi::<_, CMsgClientPICSProductInfoResponse>(request));
+
+            // large requests are answered in several parts, flagged with response_pending
+            let mut product_info = ProductInfo::default();
+            while let Some(response) = responses.next().await {
+                let response = response?;
+                product_info.apps.extend(
+                    response
+                        .apps
+                        .into_iter()
+                        .map(|app| (app.appid(), app.buffer().to_vec())),
+                );
+                product_info.packages.extend(
+                    response
+                        .packages
+                        .into_iter()
+                        .map(|package| (package.packageid(), package.buffer().to_vec())),
+                );
+                product_info.unknown_app_ids.extend(response.unknown_appids);
+                product_info
+                    .unknown_package_ids
+                    .extend(response.unknown_packageids);
+            }
+            Ok(product_info)
+        })
+    }
+
+    fn depot_key(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+    ) -> BoxFuture<'_, Result<Option<DepotKey>, Error>> {
+        Box::pin(async move {
+            let response: CMsgClientGetDepotDecryptionKeyResponse = self
+                .job(CMsgClientGetDepotDecryptionKey {
+                    depot_id: Some(depot_id),
+                    app_id: Some(app_id),
+                    ..Default::default()
+                })
+                .await?;
+            EResult::from_result(response.eresult())?;
+            match response.depot_encryption_key {
+                Some(bytes) if bytes.len() == 32 => {
+                    let mut key = [0u8; 32];
+                    key.copy_from_slice(&bytes[..]);
+                    Ok(Some(key))
+                }
+                Some(_) => Err(Error::Unexpected(
+                    "depot key has unexpected size".to_string(),
+                )),
+                None => Ok(None),
+            }
+        })
+    }
+
+    fn manifest_request_code(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+        manifest_id: u64,
+        branch: Option<String>,
+        branch_password_hash: Option<String>,
+    ) -> BoxFuture<'_, Result<u64, Error>> {
+        Box::pin(async move {
+            self.service_method(CContentServerDirectory_GetManifestRequestCode_Request {
+                app_id: Some(app_id),
+                depot_id: Some(depot_id),
+                manifest_id: Some(manifest_id),
+                app_branch: branch,
+                branch_password_hash,
+                ..Default::default()
+            })
+            .await?
+            .manifest_request_code
+            .ok_or(Error::Unexpected(
+                "failed to get manifest request code".to_string(),
+            ))
+        })
+    }
+
+    fn cdn_auth_token(
+        &self,
+        depot_id: u32,
+        host_name: String,
+    ) -> BoxFuture<'_, Result<Option<CDNAuthToken>, Error>> {
+        Box::pin(async move {
+            let response = self
+                .service_method(CContentServerDirectory_GetCDNAuthToken_Request {
+                    depot_id: Some(depot_id),
+                    host_name: Some(host_name),
+                    ..Default::default()
+                })
+                .await?;
+            let expiry = UNIX_EPOCH + Duration::from_secs(response.expiration_time() as u64);
+            Ok(response
+                .token
+                .filter(|token| !token.is_empty())
+                .map(|token| CDNAuthToken { token, expiry }))
+        })
+    }
+
+    fn request_free_license(
+        &self,
+        app_ids: Vec<u32>,
+    ) -> BoxFuture<'_, Result<GrantedLicenses, Error>> {
+        Box::pin(async move {
+            let response: CMsgClientRequestFreeLicenseResponse = self
+                .job(CMsgClientRequestFreeLicense {
+                    appids: app_ids,
+                    ..Default::default()
+                })
+                .await?;
+            EResult::from_result(response.eresult() as i32)?;
+            Ok(GrantedLicenses {
+                package_ids: response.granted_packageids,
+                app_ids: response.granted_appids,
+            })
+        })
+    }
+
+    fn servers(&self, cell_id: u32) -> BoxFuture<'_, Result<Vec<CDNServer>, Error>> {
+        Box::pin(async move {
+            Ok(self
+                .service_method(CContentServerDirectory_GetServersForSteamPipe_Request {
+                    cell_id: Some(cell_id),
+                    ..Default::default()
+                })
+                .await?
+                .servers
+                .into_iter()
+                .map(CDNServer::from)
+                .collect())
+        })
+    }
+}
@@ -1,7 +1,46 @@
 use keyvalues_parser::{Value, Vdf};
 use std::str;
 
-use crate::error::Error;
+use crate::{error::Error, utils::vdf};
+
+pub const DEFAULT_BRANCH: &str = "public";
+
+/// Platform an app is going to be installed for, used to pick depots the
+/// same way the Steam client does.
+#[derive(Debug, Clone)]
+pub struct DepotTarget {
+    /// `windows`, `macos` or `linux`, as used in depot `oslist`.
+    pub os: String,
+    /// `32` or `64`, `None` accepts depots of any architecture.
+    pub arch: Option<String>,
+    pub language: String,
+    pub branch: String,
+    pub owned_dlc: Vec<u32>,
+    pub low_violence: bool,
+}
+
+impl Default for DepotTarget {
+    fn default() -> Self {
+        Self {
+            os: "windows".to_string(),
+            arch: Some("64".to_string()),
+            language: "english".to_string(),
+            branch: DEFAULT_BRANCH.to_string(),
+            owned_dlc: Vec::new(),
+            low_violence: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedDepot {
+    /// App the depot content belongs to, which is the one to ask for depot keys
+    /// and manifest request codes. Differs from the resolved app for shared depots.
+    pub app_id: u32,
+    pub depot_id: u32,
+    /// `None` for shared depots whose owning app wasn't provided to the resolver.
+    pub manifest_id: Option<u64>,
+}
 
 #[derive(Debug)]
 pub struct Manifest {
@@ -26,9 +65,55 @@ pub struct Branch {
     pub time_updated: Option<u64>,
 }
 
+#[derive(Debug, Default)]
+pub struct DepotConfig {
+    pub oslist: Vec<String>,
+    pub osarch: Option<String>,
+    pub language: Option<String>,
+    pub low_violence: bool,
+}
+
+impl DepotConfig {
+    fn vdf_parse(&mut self, value: &[Value<'_>]) {
+        let Some(config) = value.first().and_then(Value::get_obj) else {
+            return;
+        };
+        self.oslist = vdf::get_list(config, "oslist");
+        self.osarch = vdf::get_str(config, "osarch")
+            .filter(|arch| !arch.is_empty())
+            .map(str::to_string);
+        self.language = vdf::get_str(config, "language")
+            .filter(|language| !language.is_empty())
+            .map(str::to_string);
+        self.low_violence = vdf::get_str(config, "lowviolence") == Some("1");
+    }
+
+    pub fn matches(&self, target: &DepotTarget) -> bool {
+        if !self.oslist.is_empty() && !self.oslist.contains(&target.os) {
+            return false;
+        }
+        if let (Some(osarch), Some(arch)) = (&self.osarch, &target.arch) {
+            if osarch != arch {
+                return false;
+            }
+        }
+        if let Some(language) = &self.language {
+            if *language != target.language {
+                return false;
+            }
+        }
+        !self.low_violence || target.low_violence
+    }
+}
+
 #[derive(Debug)]
 pub struct Depot {
     pub depot_id: u32,
+    pub name: Option<String>,
+    pub config: DepotConfig,
+    pub dlc_app_id: Option<u32>,
+    pub depot_from_app: Option<u32>,
+    pub shared_install: bool,
     pub manifests: Vec<Manifest>,
 }
 
@@ -36,10 +121,32 @@ impl Depot {
     pub fn new(depot_id: u32) -> Self {
         Self {
             depot_id,
+            name: None,
+            config: DepotConfig::default(),
+            dlc_app_id: None,
+            depot_from_app: None,
+            shared_install: false,
             manifests: Vec::new(),
         }
     }
 
+    /// Finds the unencrypted manifest gid for `branch`, falling back to `public`
+    /// when the branch doesn't override this depot. `None` when the branch only has
+    /// an encrypted manifest, as password protected betas do, rather than the public one.
+    pub fn manifest_gid(&self, branch: &str) -> Option<u64> {
+        let mut manifests = self
+            .manifests
+            .iter()
+            .filter(|manifest| manifest.branch == branch)
+            .peekable();
+        if manifests.peek().is_none() && branch != DEFAULT_BRANCH {
+            return self.manifest_gid(DEFAULT_BRANCH);
+        }
+        manifests
+            .find(|manifest| !manifest.encrypted)
+            .and_then(Manifest::gid)
+    }
+
     fn parse_manifests(&mut self, value: &[Value<'_>], r#type: &str) -> Result<(), Error> {
         if let Some(manifests_map) = value
             .first()
@@ -85,6 +192,18 @@ impl Depot {
     }
 
     pub fn vdf_parse(&mut self, value: &[Value<'_>]) -> Result<(), Error> {
+        let depot = value
+            .first()
+            .ok_or(Error::NoneOption)?
+            .get_obj()
+            .ok_or(Error::NoneOption)?;
+        self.name = vdf::get_str(depot, "name").map(str::to_string);
+        self.dlc_app_id = vdf::get_str(depot, "dlcappid").and_then(|id| id.parse().ok());
+        self.depot_from_app = vdf::get_str(depot, "depotfromapp").and_then(|id| id.parse().ok());
+        self.shared_install = vdf::get_str(depot, "sharedinstall") == Some("1");
+        if let Some(config) = depot.get("config") {
+            self.config.vdf_parse(config);
+        }
         self.parse_manifests(value, "manifests")?;
         self.parse_manifests(value, "encryptedmanifests")?;
         Ok(())
@@ -131,37 +250,125 @@ impl AppDepots {
                 .ok_or(Error::Unexpected("no appinfo.common.name key".to_string()))?
                 .first()
                 .map(|s| s.to_string());
-            let depots_map = &appinfo
-                .get("depots")
-                .ok_or(Error::Unexpected("no depots key".to_string()))?
-                .first()
-                .ok_or(Error::Unexpected(
-                    "no first entry of depots object".to_string(),
-                ))?
-                .get_obj()
-                .ok_or(Error::Unexpected(
-                    "failed to get depots body object".to_string(),
-                ))?
-                .0;
-            for (key, value) in depots_map {
-                if let Ok(depot_id) = key.parse::<u32>() {
-                    let mut depot = Depot::new(depot_id);
-                    depot.vdf_parse(value)?;
-                    self.depots.push(depot);
-                } else if key == "branches" {
-                    // let branches_map = &value
-                    //     .first()
-                    //     .ok_or(Error::NoneOption)?
-                    //     .get_obj()
-                    //     .ok_or(Error::NoneOption)?
-                    //     .0;
-                    // for (key, value) in branches_map {
-                    //     println!("{:?} {:?}", key, value);
-                    // }
+            self.depots_parse(
+                appinfo
+                    .get("depots")
+                    .ok_or(Error::Unexpected("no depots key".to_string()))?,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn depots_parse(&mut self, value: &[Value<'_>]) -> Result<(), Error> {
+        let depots_map = &value
+            .first()
+            .ok_or(Error::Unexpected(
+                "no first entry of depots object".to_string(),
+            ))?
+            .get_obj()
+            .ok_or(Error::Unexpected(
+                "failed to get depots body object".to_string(),
+            ))?
+            .0;
+        for (key, value) in depots_map {
+            if let Ok(depot_id) = key.parse::<u32>() {
+                let mut depot = Depot::new(depot_id);
+                depot.vdf_parse(value)?;
+                self.depots.push(depot);
+            } else if key == "branches" {
+                let branches_map = &value
+                    .first()
+                    .ok_or(Error::NoneOption)?
+                    .get_obj()
+                    .ok_or(Error::NoneOption)?
+                    .0;
+                for (key, value) in branches_map {
+                    let Some(data) = value.first().and_then(Value::get_obj) else {
+                        continue;
+                    };
+                    self.branches.push(Branch {
+                        name: key.to_string(),
+                        description: vdf::get_str(data, "description").map(str::to_string),
+                        build_id: vdf::get_str(data, "buildid")
+                            .and_then(|id| id.parse().ok())
+                            .unwrap_or_default(),
+                        time_updated: vdf::get_str(data, "timeupdated")
+                            .and_then(|time| time.parse().ok()),
+                    });
                 }
             }
         }
-
         Ok(())
     }
+
+    /// Picks the depots and manifests the Steam client would install for `target`.
+    ///
+    /// Depots shared through `depotfromapp` are looked up in `shared`, which should
+    /// hold the owning apps; when missing they're returned without a manifest.
+    /// Depots with only an encrypted manifest for the branch are left out, or come
+    /// back without a manifest when shared.
+    pub fn resolve(&self, target: &DepotTarget, shared: &[AppDepots]) -> Vec<ResolvedDepot> {
+        self.depots
+            .iter()
+            .filter(|depot| depot.config.matches(target))
+            .filter(|depot| {
+                depot
+                    .dlc_app_id
+                    .is_none_or(|dlc| target.owned_dlc.contains(&dlc))
+            })
+            .filter_map(|depot| match depot.depot_from_app {
+                Some(owner_id) if owner_id != self.app_id => Some(ResolvedDepot {
+                    app_id: owner_id,
+                    depot_id: depot.depot_id,
+                    manifest_id: shared
+                        .iter()
+                        .find(|app| app.app_id == owner_id)
+                        .and_then(|app| app.depot(depot.depot_id))
+                        .and_then(|owned| owned.manifest_gid(&target.branch)),
+                }),
+                _ => depot
+                    .manifest_gid(&target.branch)
+                    .map(|manifest_id| ResolvedDepot {
+                        app_id: self.app_id,
+                        depot_id: depot.depot_id,
+                        manifest_id: Some(manifest_id),
+                    }),
+            })
+            .collect()
+    }
+
+    pub fn depot(&self, depot_id: u32) -> Option<&Depot> {
+        self.depots.iter().find(|depot| depot.depot_id == depot_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(branch: &str, gid: &str, encrypted: bool) -> Manifest {
+        Manifest {
+            branch: branch.to_string(),
+            gid: gid.to_string(),
+            size: String::new(),
+            download: String::new(),
+            encrypted,
+        }
+    }
+
+    #[test]
+    fn manifest_gid_per_branch() {
+        let mut depot = Depot::new(11);
+        depot.manifests = vec![
+            manifest(DEFAULT_BRANCH, "1", false),
+            manifest("beta", "2", false),
+            manifest("private", "3", true),
+        ];
+        assert_eq!(depot.manifest_gid(DEFAULT_BRANCH), Some(1));
+        assert_eq!(depot.manifest_gid("beta"), Some(2));
+        assert_eq!(depot.manifest_gid("unlisted"), Some(1));
+        // not the public build in disguise
+        assert_eq!(depot.manifest_gid("private"), None);
+    }
 }
@@ -0,0 +1,348 @@
+use sha1::{Digest, Sha1};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use steam_vent::proto::{
+    content_manifest::{
+        content_manifest_payload::{file_mapping, FileMapping},
+        ContentManifestMetadata, ContentManifestPayload,
+    },
+    protobuf::Message,
+};
+use tokio::{
+    fs::{self, File},
+    io::AsyncReadExt,
+    task,
+};
+
+use crate::{
+    crypto::aes256::{self, IV_LENGTH},
+    utils::{adler, base64::base64_encode, hex, lzma, zip},
+    Error,
+};
+
+use super::{depot_key::DepotKey, manifest::DepotManifest, MANIFEST_VERSION};
+
+// SteamPipe splits files into chunks of at most 1 MiB
+const DEFAULT_CHUNK_SIZE: usize = 1024 * 1024;
+const FLAG_DIRECTORY: u32 = 64;
+const FLAG_SYMLINK: u32 = 512;
+
+/// How chunks are compressed ahead of encryption.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ChunkCompression {
+    /// Deflate in a zip archive.
+    #[default]
+    Zip,
+    /// Valve's `VZ` LZMA container, see `utils::lzma::compress` on its ratio.
+    Lzma,
+}
+
+/// Turns a local directory into a depot the way SteamPipe serves it: a manifest
+/// plus encrypted chunks, identical content ending up in a single chunk.
+#[derive(Debug, Clone)]
+pub struct DepotBuilder {
+    depot_id: u32,
+    depot_key: DepotKey,
+    chunk_size: usize,
+    compression: ChunkCompression,
+    manifest_gid: Option<u64>,
+    creation_time: Option<u32>,
+    encrypt_filenames: bool,
+}
+
+/// Output of a [`DepotBuilder`].
+#[derive(Debug, Clone)]
+pub struct BuiltDepot {
+    pub depot_id: u32,
+    pub manifest_gid: u64,
+    /// The manifest as served by the CDN, see [`CDNClient::parse_manifest`](super::CDNClient::parse_manifest).
+    pub manifest: Vec<u8>,
+    /// Encrypted chunks by hex encoded sha.
+    pub chunks: BTreeMap<String, Vec<u8>>,
+}
+
+struct Entry {
+    name: String,
+    path: PathBuf,
+    kind: EntryKind,
+}
+
+enum EntryKind {
+    File,
+    Directory,
+    Symlink(String),
+}
+
+impl DepotBuilder {
+    pub fn new(depot_id: u32, depot_key: DepotKey) -> Self {
+        Self {
+            depot_id,
+            depot_key,
+            chunk_size: DEFAULT_CHUNK_SIZE,
+            compression: ChunkCompression::default(),
+            manifest_gid: None,
+            creation_time: None,
+            encrypt_filenames: true,
+        }
+    }
+
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    pub fn compression(mut self, compression: ChunkCompression) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Defaults to one derived from the content, so unchanged input keeps its manifest gid.
+    pub fn manifest_gid(mut self, manifest_gid: u64) -> Self {
+        self.manifest_gid = Some(manifest_gid);
+        self
+    }
+
+    /// Unix time, defaults to now. Set it as well for byte identical manifests across builds.
+    pub fn creation_time(mut self, creation_time: u32) -> Self {
+        self.creation_time = Some(creation_time);
+        self
+    }
+
+    /// Steam encrypts filenames in the manifests it serves, as does this by default.
+    pub fn encrypt_filenames(mut self, encrypt_filenames: bool) -> Self {
+        self.encrypt_filenames = encrypt_filenames;
+        self
+    }
+
+    pub async fn build<P: AsRef<Path>>(&self, dir: P) -> Result<BuiltDepot, Error> {
+        let mut chunks = BTreeMap::new();
+        let mut mappings = Vec::new();
+        let mut original_size = 0;
+        for entry in walk(dir.as_ref()).await? {
+            let mut mapping = FileMapping {
+                filename: Some(entry.name.clone()),
+                sha_filename: Some(Sha1::digest(entry.name.to_lowercase()).to_vec()),
+                ..Default::default()
+            };
+            match entry.kind {
+                EntryKind::Directory => {
+                    mapping.size = Some(0);
+                    mapping.flags = Some(FLAG_DIRECTORY);
+                }
+                EntryKind::Symlink(target) => {
+                    mapping.size = Some(0);
+                    mapping.flags = Some(FLAG_SYMLINK);
+                    mapping.linktarget = Some(target);
+                }
+                EntryKind::File => {
+                    let (size, sha_content, file_chunks) =
+                        self.chunk_file(&entry.path, &mut chunks).await?;
+                    original_size += size;
+                    mapping.size = Some(size);
+                    mapping.flags = Some(0);
+                    mapping.sha_content = Some(sha_content);
+                    mapping.chunks = file_chunks;
+                }
+            }
+            mappings.push(mapping);
+        }
+
+        let clear_payload = ContentManifestPayload {
+            mappings,
+            ..Default::default()
+        };
+        let clear_bytes = clear_payload
+            .write_to_bytes()
+            .map_err(|err| Error::Unexpected(err.to_string()))?;
+        let manifest_gid = self.manifest_gid.unwrap_or_else(|| {
+            let digest = Sha1::digest(&clear_bytes);
+            u64::from_le_bytes(digest[..8].try_into().unwrap())
+        });
+
+        let mut metadata = ContentManifestMetadata {
+            depot_id: Some(self.depot_id),
+            gid_manifest: Some(manifest_gid),
+            creation_time: Some(self.creation_time.unwrap_or_else(|| {
+                SystemTime::now()
+                    .duration_since(UNIX_EPOCH)
+                    .unwrap_or_default()
+                    .as_secs() as u32
+            })),
+            filenames_encrypted: Some(self.encrypt_filenames),
+            cb_disk_original: Some(original_size),
+            cb_disk_compressed: Some(
+                chunks
+                    .values()
+                    .map(|chunk: &Vec<u8>| chunk.len() as u64)
+                    .sum(),
+            ),
+            unique_chunks: Some(chunks.len() as u32),
+            crc_clear: Some(crc32fast::hash(&clear_bytes)),
+            ..Default::default()
+        };
+
+        let payload = if self.encrypt_filenames {
+            let mut payload = clear_payload;
+            for mapping in &mut payload.mappings {
+                let name = mapping.filename();
+                let iv = iv_for(name.as_bytes());
+                mapping.filename = Some(base64_encode(aes256::encrypt_cbc_with_iv(
+                    name.as_bytes(),
+                    self.depot_key,
+                    iv,
+                )));
+            }
+            let encrypted_bytes = payload
+                .write_to_bytes()
+                .map_err(|err| Error::Unexpected(err.to_string()))?;
+            metadata.crc_encrypted = Some(crc32fast::hash(&encrypted_bytes));
+            payload
+        } else {
+            clear_payload
+        };
+
+        Ok(BuiltDepot {
+            depot_id: self.depot_id,
+            manifest_gid,
+            manifest: DepotManifest::serialize(&payload, &metadata)?,
+            chunks,
+        })
+    }
+
+    async fn chunk_file(
+        &self,
+        path: &Path,
+        chunks: &mut BTreeMap<String, Vec<u8>>,
+    ) -> Result<(u64, Vec<u8>, Vec<file_mapping::ChunkData>), Error> {
+        let mut file = File::open(path).await?;
+        let mut sha_content = Sha1::new();
+        let mut file_chunks = Vec::new();
+        let mut offset = 0u64;
+        loop {
+            let data = read_chunk(&mut file, self.chunk_size).await?;
+            if data.is_empty() {
+                break;
+            }
+            sha_content.update(&data);
+
+            let sha = Sha1::digest(&data).to_vec();
+            let id = hex::encode(&sha);
+            let crc = adler::steam_adler32(&data);
+            let original_size = data.len() as u32;
+            let compressed_size = match chunks.get(&id) {
+                Some(chunk) => chunk.len(),
+                None => {
+                    let chunk = self.pack_chunk(data, &sha).await?;
+                    let compressed_size = chunk.len();
+                    chunks.insert(id, chunk);
+                    compressed_size
+                }
+            };
+            file_chunks.push(file_mapping::ChunkData {
+                sha: Some(sha),
+                crc: Some(crc),
+                offset: Some(offset),
+                cb_original: Some(original_size),
+                cb_compressed: Some(compressed_size as u32),
+                ..Default::default()
+            });
+            offset += original_size as u64;
+        }
+        Ok((offset, sha_content.finalize().to_vec(), file_chunks))
+    }
+
+    async fn pack_chunk(&self, data: Vec<u8>, sha: &[u8]) -> Result<Vec<u8>, Error> {
+        let compressed = match self.compression {
+            ChunkCompression::Zip => {
+                task::spawn_blocking(move || zip::compress("z", &data)).await??
+            }
+            ChunkCompression::Lzma => lzma::compress(&data).await?,
+        };
+        Ok(aes256::encrypt_cbc_with_iv(
+            &compressed,
+            self.depot_key,
+            iv_for(sha),
+        ))
+    }
+}
+
+impl BuiltDepot {
+    /// Writes `depot/{id}/manifest/{gid}/5` and `depot/{id}/chunk/{sha}` under `root`,
+    /// ready to be served as a content server.
+    pub async fn write_cdn_layout<P: AsRef<Path>>(&self, root: P) -> Result<(), Error> {
+        let depot_dir = root.as_ref().join("depot").join(self.depot_id.to_string());
+        let manifest_dir = depot_dir
+            .join("manifest")
+            .join(self.manifest_gid.to_string());
+        fs::create_dir_all(&manifest_dir).await?;
+        fs::write(
+            manifest_dir.join(MANIFEST_VERSION.to_string()),
+            &self.manifest,
+        )
+        .await?;
+
+        let chunk_dir = depot_dir.join("chunk");
+        fs::create_dir_all(&chunk_dir).await?;
+        for (id, chunk) in &self.chunks {
+            fs::write(chunk_dir.join(id), chunk).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Directories and files below `root` by their depot name, `\` separated as
+/// steam stores them, in a stable order. Symlinks are kept as links, not followed.
+async fn walk(root: &Path) -> Result<Vec<Entry>, Error> {
+    let mut entries = Vec::new();
+    let mut pending = vec![root.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut read_dir = fs::read_dir(&dir).await?;
+        while let Some(dir_entry) = read_dir.next_entry().await? {
+            let path = dir_entry.path();
+            let file_type = dir_entry.file_type().await?;
+            let name = path
+                .strip_prefix(root)
+                .map_err(|err| Error::Unexpected(err.to_string()))?
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("\\");
+            let kind = if file_type.is_symlink() {
+                let target = fs::read_link(&path).await?;
+                EntryKind::Symlink(target.to_string_lossy().into_owned())
+            } else if file_type.is_dir() {
+                pending.push(path.clone());
+                EntryKind::Directory
+            } else {
+                EntryKind::File
+            };
+            entries.push(Entry { name, path, kind });
+        }
+    }
+    entries.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(entries)
+}
+
+async fn read_chunk(file: &mut File, chunk_size: usize) -> Result<Vec<u8>, Error> {
+    let mut data = vec![0u8; chunk_size];
+    let mut filled = 0;
+    while filled < chunk_size {
+        let read = file.read(&mut data[filled..]).await?;
+        if read == 0 {
+            break;
+        }
+        filled += read;
+    }
+    data.truncate(filled);
+    Ok(data)
+}
+
+/// Derived from the content rather than random so builds are reproducible, equal
+/// plaintexts are already apparent from chunk ids and filename hashes.
+fn iv_for(data: &[u8]) -> [u8; IV_LENGTH] {
+    let digest = Sha1::digest(data);
+    digest[..IV_LENGTH].try_into().unwrap()
+}
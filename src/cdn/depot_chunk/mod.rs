@@ -7,12 +7,13 @@ use crate::{
     Error,
 };
 
-pub async fn decrypt_and_decompress(data: &mut [u8], key: [u8; 32]) -> Result<Vec<u8>, Error> {
-    if data.len() <= IV_LENGTH {
-        return Err(Error::Eof("data is too small".to_string()));
-    }
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
 
-    let decrypted = aes256::decrypt_cbc_with_iv_extraction(data, key)?;
+/// Decrypts and unpacks a downloaded chunk. A body that fails to decrypt is reported as
+/// `Decompress`, downloads check the key once up front, so that it's retried as corruption.
+pub async fn decrypt_and_decompress(data: &mut [u8], key: [u8; 32]) -> Result<Vec<u8>, Error> {
+    let decrypted = decrypt_container(data, key)
+        .ok_or_else(|| Error::Decompress("chunk doesn't decrypt to a known container".into()))?;
     if lzma::is_vz(&decrypted) {
         Ok(lzma::decompress(&decrypted).await?)
     } else {
@@ -24,3 +25,19 @@ pub async fn decrypt_and_decompress(data: &mut [u8], key: [u8; 32]) -> Result<Ve
         Ok(buffer)
     }
 }
+
+/// Decrypts a chunk, reporting a wrong key when the plaintext isn't a known container.
+pub fn decrypt(data: &mut [u8], key: [u8; 32]) -> Result<Vec<u8>, Error> {
+    if data.len() <= IV_LENGTH {
+        return Err(Error::Eof("data is too small".to_string()));
+    }
+    decrypt_container(data, key).ok_or(Error::WrongDepotKey)
+}
+
+fn decrypt_container(data: &mut [u8], key: [u8; 32]) -> Option<Vec<u8>> {
+    if data.len() <= IV_LENGTH {
+        return None;
+    }
+    let decrypted = aes256::decrypt_cbc_with_iv_extraction(data, key).ok()?;
+    (lzma::is_vz(&decrypted) || decrypted.starts_with(ZIP_MAGIC)).then_some(decrypted)
+}
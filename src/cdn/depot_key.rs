@@ -0,0 +1,157 @@
+use futures::future::BoxFuture;
+use std::{
+    collections::BTreeMap,
+    fmt::Debug,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use tokio::fs;
+
+use crate::{utils::hex, Error};
+
+use super::connection::SteamConnection;
+
+pub type DepotKey = [u8; 32];
+
+/// Source of depot decryption keys, consulted by `CDNClient` before asking the CM.
+pub trait DepotKeyProvider: Debug + Send + Sync {
+    fn depot_key(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+    ) -> BoxFuture<'_, Result<Option<DepotKey>, Error>>;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryKeyProvider {
+    keys: RwLock<BTreeMap<u32, DepotKey>>,
+}
+
+impl MemoryKeyProvider {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, depot_id: u32, key: DepotKey) {
+        self.keys.write().unwrap().insert(depot_id, key);
+    }
+
+    pub fn get(&self, depot_id: u32) -> Option<DepotKey> {
+        self.keys.read().unwrap().get(&depot_id).copied()
+    }
+
+    pub fn keys(&self) -> BTreeMap<u32, DepotKey> {
+        self.keys.read().unwrap().clone()
+    }
+}
+
+impl FromIterator<(u32, DepotKey)> for MemoryKeyProvider {
+    fn from_iter<T: IntoIterator<Item = (u32, DepotKey)>>(iter: T) -> Self {
+        Self {
+            keys: RwLock::new(iter.into_iter().collect()),
+        }
+    }
+}
+
+impl DepotKeyProvider for MemoryKeyProvider {
+    fn depot_key(
+        &self,
+        _app_id: u32,
+        depot_id: u32,
+    ) -> BoxFuture<'_, Result<Option<DepotKey>, Error>> {
+        let key = self.get(depot_id);
+        Box::pin(async move { Ok(key) })
+    }
+}
+
+/// Keys stored as `depot_id;hexkey` lines, the format of `depot_keys.txt`.
+#[derive(Debug)]
+pub struct FileKeyProvider {
+    path: PathBuf,
+    keys: MemoryKeyProvider,
+}
+
+impl FileKeyProvider {
+    pub async fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        let path = path.as_ref().to_path_buf();
+        let keys = match fs::read_to_string(&path).await {
+            Ok(content) => parse_key_file(&content)?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => MemoryKeyProvider::new(),
+            Err(err) => return Err(err.into()),
+        };
+        Ok(Self { path, keys })
+    }
+
+    pub fn insert(&self, depot_id: u32, key: DepotKey) {
+        self.keys.insert(depot_id, key);
+    }
+
+    pub async fn save(&self) -> Result<(), Error> {
+        write_key_file(&self.path, &self.keys.keys()).await
+    }
+}
+
+impl DepotKeyProvider for FileKeyProvider {
+    fn depot_key(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+    ) -> BoxFuture<'_, Result<Option<DepotKey>, Error>> {
+        self.keys.depot_key(app_id, depot_id)
+    }
+}
+
+/// Asks the connected CM, requires the account to own the depot.
+#[derive(Debug)]
+pub struct CMKeyProvider {
+    connection: Arc<dyn SteamConnection>,
+}
+
+impl CMKeyProvider {
+    pub fn new(connection: Arc<dyn SteamConnection>) -> Self {
+        Self { connection }
+    }
+}
+
+impl DepotKeyProvider for CMKeyProvider {
+    fn depot_key(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+    ) -> BoxFuture<'_, Result<Option<DepotKey>, Error>> {
+        self.connection.depot_key(app_id, depot_id)
+    }
+}
+
+fn parse_key_file(content: &str) -> Result<MemoryKeyProvider, Error> {
+    content
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| {
+            let (depot_id, key) = line.split_once(';').ok_or(Error::Unexpected(format!(
+                "malformed depot key line: {line}"
+            )))?;
+            let depot_id = depot_id
+                .trim()
+                .parse::<u32>()
+                .map_err(|err| Error::Unexpected(format!("malformed depot id: {err}")))?;
+            let key = hex::decode(key.trim())?.try_into().map_err(|_| {
+                Error::Unexpected(format!("depot key of {depot_id} isn't 32 bytes"))
+            })?;
+            Ok((depot_id, key))
+        })
+        .collect()
+}
+
+pub(crate) async fn write_key_file(
+    path: &Path,
+    keys: &BTreeMap<u32, DepotKey>,
+) -> Result<(), Error> {
+    let content = keys
+        .iter()
+        .map(|(depot_id, key)| format!("{depot_id};{}\n", hex::encode(key)))
+        .collect::<String>();
+    fs::write(path, content).await?;
+    Ok(())
+}
@@ -0,0 +1,267 @@
+use futures::{
+    future::BoxFuture,
+    stream::{self, BoxStream},
+};
+use std::{
+    collections::BTreeMap,
+    sync::Mutex,
+    time::{Duration, SystemTime},
+};
+use steam_vent::EResult;
+use tokio::sync::watch;
+
+use crate::{web_api::content_service::CDNServer, Error};
+
+use super::{
+    connection::{AccessTokens, CDNAuthToken, ProductInfo, SteamConnection},
+    depot_key::DepotKey,
+    license::{GrantedLicenses, License},
+};
+
+/// Calls of [`SteamConnection`], to script failures and count requests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum FakeCall {
+    AccessTokens,
+    ProductInfo,
+    DepotKey,
+    ManifestRequestCode,
+    CDNAuthToken,
+    RequestFreeLicense,
+    Servers,
+}
+
+/// In-memory stand-in for a logged on connection, answering from whatever
+/// was put into it.
+#[derive(Debug)]
+pub struct FakeConnection {
+    cell_id: u32,
+    licenses: watch::Sender<Option<Vec<License>>>,
+    state: Mutex<FakeState>,
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    apps: BTreeMap<u32, Vec<u8>>,
+    packages: BTreeMap<u32, Vec<u8>>,
+    depot_keys: BTreeMap<u32, DepotKey>,
+    request_codes: BTreeMap<u64, u64>,
+    cdn_auth_tokens: BTreeMap<u32, String>,
+    free_licenses: BTreeMap<u32, u32>,
+    servers: Vec<CDNServer>,
+    failures: BTreeMap<FakeCall, EResult>,
+    calls: BTreeMap<FakeCall, usize>,
+}
+
+impl Default for FakeConnection {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
+
+impl FakeConnection {
+    pub fn new(cell_id: u32) -> Self {
+        Self {
+            cell_id,
+            licenses: watch::Sender::new(None),
+            state: Mutex::new(FakeState::default()),
+        }
+    }
+
+    /// Pushes a license list, like steam does after logon.
+    pub fn set_licenses(&self, licenses: Vec<License>) {
+        self.licenses.send_replace(Some(licenses));
+    }
+
+    /// `buffer` is the app's text KeyValues, as PICS returns it.
+    pub fn add_app<B: Into<Vec<u8>>>(&self, app_id: u32, buffer: B) {
+        self.state().apps.insert(app_id, buffer.into());
+    }
+
+    /// `buffer` is the package's binary KeyValues behind the leading u32.
+    pub fn add_package<B: Into<Vec<u8>>>(&self, package_id: u32, buffer: B) {
+        self.state().packages.insert(package_id, buffer.into());
+    }
+
+    pub fn add_depot_key(&self, depot_id: u32, key: DepotKey) {
+        self.state().depot_keys.insert(depot_id, key);
+    }
+
+    pub fn add_request_code(&self, manifest_id: u64, code: u64) {
+        self.state().request_codes.insert(manifest_id, code);
+    }
+
+    pub fn add_cdn_auth_token<T: Into<String>>(&self, depot_id: u32, token: T) {
+        self.state().cdn_auth_tokens.insert(depot_id, token.into());
+    }
+
+    /// Requesting a free license for `app_id` grants `package_id`.
+    pub fn add_free_license(&self, app_id: u32, package_id: u32) {
+        self.state().free_licenses.insert(app_id, package_id);
+    }
+
+    pub fn set_servers(&self, servers: Vec<CDNServer>) {
+        self.state().servers = servers;
+    }
+
+    /// Makes every `call` fail with `eresult` until cleared with `None`.
+    pub fn fail(&self, call: FakeCall, eresult: Option<EResult>) {
+        let mut state = self.state();
+        match eresult {
+            Some(eresult) => state.failures.insert(call, eresult),
+            None => state.failures.remove(&call),
+        };
+    }
+
+    pub fn calls(&self, call: FakeCall) -> usize {
+        self.state().calls.get(&call).copied().unwrap_or_default()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, FakeState> {
+        self.state.lock().unwrap()
+    }
+
+    fn answer<T, F: FnOnce(&FakeState) -> Result<T, Error>>(
+        &self,
+        call: FakeCall,
+        answer: F,
+    ) -> BoxFuture<'_, Result<T, Error>>
+    where
+        T: Send + 'static,
+    {
+        let mut state = self.state();
+        *state.calls.entry(call).or_default() += 1;
+        let result = match state.failures.get(&call) {
+            Some(eresult) => Err(Error::EResult(*eresult)),
+            None => answer(&state),
+        };
+        Box::pin(async move { result })
+    }
+}
+
+impl SteamConnection for FakeConnection {
+    fn cell_id(&self) -> u32 {
+        self.cell_id
+    }
+
+    fn license_lists(&self) -> BoxStream<'static, Vec<License>> {
+        let mut licenses = self.licenses.subscribe();
+        // a list set ahead of subscribing still counts
+        licenses.mark_changed();
+        Box::pin(stream::unfold(licenses, |mut licenses| async move {
+            loop {
+                licenses.changed().await.ok()?;
+                let list = licenses.borrow_and_update().clone();
+                if let Some(list) = list {
+                    return Some((list, licenses));
+                }
+            }
+        }))
+    }
+
+    fn access_tokens(
+        &self,
+        app_ids: Vec<u32>,
+        package_ids: Vec<u32>,
+    ) -> BoxFuture<'_, Result<AccessTokens, Error>> {
+        self.answer(FakeCall::AccessTokens, |_| {
+            Ok(AccessTokens {
+                apps: app_ids.into_iter().map(|app_id| (app_id, 0)).collect(),
+                packages: package_ids
+                    .into_iter()
+                    .map(|package_id| (package_id, 0))
+                    .collect(),
+            })
+        })
+    }
+
+    fn product_info(&self, tokens: AccessTokens) -> BoxFuture<'_, Result<ProductInfo, Error>> {
+        self.answer(FakeCall::ProductInfo, |state| {
+            let mut product_info = ProductInfo::default();
+            for app_id in tokens.apps.into_keys() {
+                match state.apps.get(&app_id) {
+                    Some(buffer) => {
+                        product_info.apps.insert(app_id, buffer.clone());
+                    }
+                    None => product_info.unknown_app_ids.push(app_id),
+                }
+            }
+            for package_id in tokens.packages.into_keys() {
+                match state.packages.get(&package_id) {
+                    Some(buffer) => {
+                        product_info.packages.insert(package_id, buffer.clone());
+                    }
+                    None => product_info.unknown_package_ids.push(package_id),
+                }
+            }
+            Ok(product_info)
+        })
+    }
+
+    fn depot_key(
+        &self,
+        _app_id: u32,
+        depot_id: u32,
+    ) -> BoxFuture<'_, Result<Option<DepotKey>, Error>> {
+        self.answer(FakeCall::DepotKey, |state| {
+            state
+                .depot_keys
+                .get(&depot_id)
+                .copied()
+                .map(Some)
+                .ok_or(Error::EResult(EResult::AccessDenied))
+        })
+    }
+
+    fn manifest_request_code(
+        &self,
+        _app_id: u32,
+        _depot_id: u32,
+        manifest_id: u64,
+        _branch: Option<String>,
+        _branch_password_hash: Option<String>,
+    ) -> BoxFuture<'_, Result<u64, Error>> {
+        self.answer(FakeCall::ManifestRequestCode, |state| {
+            state
+                .request_codes
+                .get(&manifest_id)
+                .copied()
+                .ok_or(Error::EResult(EResult::AccessDenied))
+        })
+    }
+
+    fn cdn_auth_token(
+        &self,
+        depot_id: u32,
+        _host_name: String,
+    ) -> BoxFuture<'_, Result<Option<CDNAuthToken>, Error>> {
+        self.answer(FakeCall::CDNAuthToken, |state| {
+            Ok(state
+                .cdn_auth_tokens
+                .get(&depot_id)
+                .map(|token| CDNAuthToken {
+                    token: token.clone(),
+                    expiry: SystemTime::now() + Duration::from_secs(60 * 60),
+                }))
+        })
+    }
+
+    fn request_free_license(
+        &self,
+        app_ids: Vec<u32>,
+    ) -> BoxFuture<'_, Result<GrantedLicenses, Error>> {
+        self.answer(FakeCall::RequestFreeLicense, |state| {
+            let granted = app_ids
+                .into_iter()
+                .filter_map(|app_id| Some((app_id, *state.free_licenses.get(&app_id)?)))
+                .collect::<Vec<(u32, u32)>>();
+            Ok(GrantedLicenses {
+                app_ids: granted.iter().map(|(app_id, _)| *app_id).collect(),
+                package_ids: granted.iter().map(|(_, package_id)| *package_id).collect(),
+            })
+        })
+    }
+
+    fn servers(&self, _cell_id: u32) -> BoxFuture<'_, Result<Vec<CDNServer>, Error>> {
+        self.answer(FakeCall::Servers, |state| Ok(state.servers.clone()))
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use crate::web_api::content_service::CDNServer;
+
+// failures and successes lose half their weight every minute
+const DECAY_HALF_LIFE: Duration = Duration::from_secs(60);
+const EWMA_WEIGHT: f64 = 0.3;
+// scores are in milliseconds, these put the other factors on the same scale
+const UNKNOWN_LATENCY_MS: f64 = 150.0;
+const PRIORITY_CLASS_MS: f64 = 100.0;
+const CELL_MISMATCH_MS: f64 = 50.0;
+const MEGABYTE: f64 = 1024.0 * 1024.0;
+const FAILURE_RATE_FACTOR: f64 = 20.0;
+const LATENCY_WINDOW: usize = 200;
+const LATENCY_MIN_SAMPLES: usize = 20;
+
+/// Observed behaviour of a content server, folded into a score where lower is better.
+#[derive(Debug, Clone)]
+pub struct ServerHealth {
+    latency_ms: Option<f64>,
+    throughput: Option<f64>,
+    successes: f64,
+    failures: f64,
+    updated: Instant,
+}
+
+impl Default for ServerHealth {
+    fn default() -> Self {
+        Self {
+            latency_ms: None,
+            throughput: None,
+            successes: 0.0,
+            failures: 0.0,
+            updated: Instant::now(),
+        }
+    }
+}
+
+impl ServerHealth {
+    fn decay(&mut self) {
+        let now = Instant::now();
+        let factor = decay_factor(now - self.updated);
+        self.successes *= factor;
+        self.failures *= factor;
+        self.updated = now;
+    }
+
+    pub fn record_success(&mut self, latency: Duration, bytes: usize, transfer: Duration) {
+        self.decay();
+        self.successes += 1.0;
+        self.latency_ms = Some(ewma(self.latency_ms, latency.as_secs_f64() * 1000.0));
+        // tiny bodies say more about latency than bandwidth
+        if bytes >= 64 * 1024 && !transfer.is_zero() {
+            self.throughput = Some(ewma(self.throughput, bytes as f64 / transfer.as_secs_f64()));
+        }
+    }
+
+    /// A request abandoned after `latency` for a faster one, so a lower bound of its latency.
+    pub fn record_slow(&mut self, latency: Duration) {
+        self.decay();
+        self.latency_ms = Some(ewma(self.latency_ms, latency.as_secs_f64() * 1000.0));
+    }
+
+    pub fn record_failure(&mut self) {
+        self.decay();
+        self.failures += 1.0;
+    }
+
+    pub fn failure_rate(&self) -> f64 {
+        let factor = decay_factor(self.updated.elapsed());
+        let (successes, failures) = (self.successes * factor, self.failures * factor);
+        // the prior lets a quiet server recover as its failures decay away
+        failures / (successes + failures + 1.0)
+    }
+
+    pub fn latency(&self) -> Option<Duration> {
+        self.latency_ms
+            .map(|latency| Duration::from_secs_f64(latency / 1000.0))
+    }
+
+    /// Bytes per second over recent chunk downloads.
+    pub fn throughput(&self) -> Option<f64> {
+        self.throughput
+    }
+
+    pub fn score(&self, server: &CDNServer, cell_id: u32) -> f64 {
+        let mut score = self.latency_ms.unwrap_or(UNKNOWN_LATENCY_MS)
+            + server.weighted_load as f64
+            + server.priority_class as f64 * PRIORITY_CLASS_MS;
+        if let Some(throughput) = self.throughput {
+            score += MEGABYTE / throughput * 1000.0;
+        }
+        if server.cell_id != cell_id {
+            score += CELL_MISMATCH_MS;
+        }
+        score * (1.0 + self.failure_rate() * FAILURE_RATE_FACTOR)
+    }
+}
+
+fn decay_factor(elapsed: Duration) -> f64 {
+    0.5f64.powf(elapsed.as_secs_f64() / DECAY_HALF_LIFE.as_secs_f64())
+}
+
+fn ewma(current: Option<f64>, sample: f64) -> f64 {
+    match current {
+        Some(current) => current + EWMA_WEIGHT * (sample - current),
+        None => sample,
+    }
+}
+
+/// Durations of recent requests, used to tell when one is unusually slow.
+#[derive(Debug, Default)]
+pub(crate) struct LatencyWindow {
+    samples: VecDeque<Duration>,
+}
+
+impl LatencyWindow {
+    pub fn record(&mut self, sample: Duration) {
+        if self.samples.len() == LATENCY_WINDOW {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(sample);
+    }
+
+    /// `None` until enough samples were seen for the percentile to mean anything.
+    pub fn percentile(&self, percentile: f64) -> Option<Duration> {
+        if self.samples.len() < LATENCY_MIN_SAMPLES {
+            return None;
+        }
+        let mut samples = self.samples.iter().copied().collect::<Vec<Duration>>();
+        samples.sort_unstable();
+        let index = ((samples.len() - 1) as f64 * percentile).round() as usize;
+        samples.get(index).copied()
+    }
+}
+
+/// What of a `ServerHealth` survives a restart.
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HealthSnapshot {
+    latency_ms: Option<f64>,
+    throughput: Option<f64>,
+    successes: f64,
+    failures: f64,
+}
+
+impl ServerHealth {
+    pub(crate) fn snapshot(&self) -> HealthSnapshot {
+        let factor = decay_factor(self.updated.elapsed());
+        HealthSnapshot {
+            latency_ms: self.latency_ms,
+            throughput: self.throughput,
+            successes: self.successes * factor,
+            failures: self.failures * factor,
+        }
+    }
+
+    /// Counts decay over `age`, the time the snapshot spent on disk.
+    pub(crate) fn restore(snapshot: HealthSnapshot, age: Duration) -> Self {
+        let factor = decay_factor(age);
+        Self {
+            latency_ms: snapshot.latency_ms,
+            throughput: snapshot.throughput,
+            successes: snapshot.successes * factor,
+            failures: snapshot.failures * factor,
+            updated: Instant::now(),
+        }
+    }
+}
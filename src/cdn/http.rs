@@ -0,0 +1,47 @@
+use reqwest::{Certificate, Client, Proxy};
+use std::time::Duration;
+
+use crate::Error;
+
+/// Settings for the client used for server discovery, manifests and chunks alike.
+#[derive(Debug, Clone, Default)]
+pub struct HttpOptions {
+    /// `http://`, `https://` or `socks5://` proxy url.
+    pub proxy: Option<String>,
+    pub connect_timeout: Option<Duration>,
+    /// Longest wait for the next piece of a response, enforced by `CDNClient`
+    /// itself so it applies to a preconfigured client as well.
+    pub read_timeout: Option<Duration>,
+    pub user_agent: Option<String>,
+    /// PEM encoded certificates trusted in addition to the built in roots.
+    pub root_certificates: Vec<Vec<u8>>,
+    pub pool_max_idle_per_host: Option<usize>,
+    pub pool_idle_timeout: Option<Duration>,
+}
+
+impl HttpOptions {
+    pub fn build(&self) -> Result<Client, Error> {
+        let mut builder = Client::builder();
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy)?);
+        }
+        if let Some(connect_timeout) = self.connect_timeout {
+            builder = builder.connect_timeout(connect_timeout);
+        }
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent);
+        }
+        for pem in &self.root_certificates {
+            for certificate in Certificate::from_pem_bundle(pem)? {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        if let Some(max_idle) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max_idle);
+        }
+        if let Some(idle_timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(idle_timeout);
+        }
+        Ok(builder.build()?)
+    }
+}
@@ -1,147 +1,626 @@
-use reqwest::{Client, Response};
-use std::sync::Arc;
-use steam_vent::{
-    proto::steammessages_clientserver_appinfo::{
-        cmsg_client_picsproduct_info_request::AppInfo, CMsgClientPICSAccessTokenRequest,
-        CMsgClientPICSAccessTokenResponse, CMsgClientPICSProductInfoRequest,
-        CMsgClientPICSProductInfoResponse,
-    },
-    Connection, ConnectionTrait,
+use bytes::{Bytes, BytesMut};
+use futures::{
+    future::{select, Either},
+    stream::BoxStream,
+    StreamExt,
+};
+use itertools::Itertools;
+use reqwest::{header::HOST, Client, RequestBuilder, Response, StatusCode};
+use std::{
+    collections::{HashMap, HashSet},
+    future::Future,
+    net::SocketAddr,
+    path::PathBuf,
+    pin::pin,
+    sync::{Arc, RwLock},
+    time::SystemTime,
+};
+use tokio::{
+    sync::{watch, Mutex, OwnedSemaphorePermit, Semaphore},
+    time::{sleep, timeout, Duration, Instant},
 };
-use tokio::sync::Mutex;
 
-use crate::{
-    web_api::{self, content_service::CDNServer},
-    Error,
+use crate::{utils::adler, web_api::content_service::CDNServer, Error};
+
+use super::{
+    builder::{ClientConfig, Verification},
+    concurrency::AdaptiveLimit,
+    connection::{ProductInfo, SteamConnection},
+    depot_chunk,
+    depot_key::{CMKeyProvider, DepotKeyProvider, MemoryKeyProvider},
+    health::{LatencyWindow, ServerHealth},
+    license::License,
+    manifest::file::ChunkData,
+    server_list,
+    server_source::{ServerSource, WebApiServerSource},
 };
 
-use super::depot_chunk;
+const LICENSE_LIST_TIMEOUT: Duration = Duration::from_secs(10);
+const SERVER_MAINTENANCE_INTERVAL: Duration = Duration::from_secs(60);
+// chunks slower than this share of recent ones get a duplicate request
+const HEDGE_PERCENTILE: f64 = 0.95;
+// steam hands out request codes valid for five minutes, keep a margin
+const REQUEST_CODE_TTL: Duration = Duration::from_secs(4 * 60);
+
+pub(crate) type RequestCodeKey = (u32, u32, u64, String);
+
+#[derive(Debug, Default)]
+pub(crate) struct RequestCodeCache {
+    codes: std::sync::Mutex<HashMap<RequestCodeKey, (u64, Instant)>>,
+}
+
+impl RequestCodeCache {
+    pub fn get(&self, key: &RequestCodeKey) -> Option<u64> {
+        let mut codes = self.codes.lock().unwrap();
+        codes.retain(|_, (_, fetched)| fetched.elapsed() < REQUEST_CODE_TTL);
+        codes.get(key).map(|(code, _)| *code)
+    }
+
+    pub fn insert(&self, key: RequestCodeKey, code: u64) {
+        self.codes
+            .lock()
+            .unwrap()
+            .insert(key, (code, Instant::now()));
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct ServerEntry {
+    pub server: CDNServer,
+    pub health: ServerHealth,
+    connections: Arc<Semaphore>,
+}
+
+impl ServerEntry {
+    fn new(server: CDNServer, health: ServerHealth, connections: usize) -> Self {
+        Self {
+            server,
+            health,
+            connections: Arc::new(Semaphore::new(connections)),
+        }
+    }
+
+    /// Load figures change between fetches of the list, the address doesn't.
+    fn is(&self, server: &CDNServer) -> bool {
+        self.server.host == server.host
+            && self.server.port == server.port
+            && self.server.vhost == server.vhost
+    }
+}
 
 #[derive(Debug)]
 pub(crate) struct InnerClient {
-    pub connection: Arc<Connection>,
+    pub connection: Arc<dyn SteamConnection>,
     web_client: Client,
-    pub servers: Arc<Mutex<Vec<(CDNServer, u32)>>>,
+    pub config: ClientConfig,
+    pub servers: Mutex<Vec<ServerEntry>>,
+    servers_fetched: std::sync::Mutex<Option<SystemTime>>,
+    refreshing_servers: Mutex<()>,
+    licenses: watch::Receiver<Option<Vec<License>>>,
+    pub key_providers: RwLock<Vec<Arc<dyn DepotKeyProvider>>>,
+    pub cm_keys: CMKeyProvider,
+    pub session_keys: MemoryKeyProvider,
+    cdn_auth_tokens: std::sync::Mutex<HashMap<(u32, String), (String, SystemTime)>>,
+    pub request_codes: RequestCodeCache,
+    pub server_source: RwLock<Arc<dyn ServerSource>>,
+    pub lancache: RwLock<Option<SocketAddr>>,
+    chunk_latencies: std::sync::Mutex<LatencyWindow>,
+    pub adaptive_limit: Option<AdaptiveLimit>,
+    // held while checking so concurrent downloads of a depot check its key once
+    checked_keys: Mutex<HashSet<(u32, [u8; 32])>>,
 }
 
 impl InnerClient {
-    pub fn new(connection: Arc<Connection>) -> Self {
+    pub fn new(
+        connection: Arc<dyn SteamConnection>,
+        web_client: Client,
+        server_source: Option<Arc<dyn ServerSource>>,
+        (licenses, mut license_lists): (Option<Vec<License>>, BoxStream<'static, Vec<License>>),
+        config: ClientConfig,
+    ) -> Self {
+        // steam only pushes the license list after logon and on changes,
+        // so keep listening for the lifetime of the client
+        let (licenses_tx, licenses) = watch::channel(licenses);
+        tokio::spawn(async move {
+            while let Some(licenses) = license_lists.next().await {
+                if licenses_tx.send(Some(licenses)).is_err() {
+                    break;
+                }
+            }
+        });
+
         Self {
+            cm_keys: CMKeyProvider::new(connection.clone()),
             connection,
-            web_client: Client::new(),
-            servers: Arc::new(Mutex::new(Vec::new())),
+            server_source: RwLock::new(
+                server_source
+                    .unwrap_or_else(|| Arc::new(WebApiServerSource::new(web_client.clone()))),
+            ),
+            web_client,
+            servers: Mutex::new(Vec::new()),
+            servers_fetched: std::sync::Mutex::new(None),
+            refreshing_servers: Mutex::new(()),
+            licenses,
+            key_providers: RwLock::new(Vec::new()),
+            session_keys: MemoryKeyProvider::new(),
+            cdn_auth_tokens: std::sync::Mutex::new(HashMap::new()),
+            request_codes: RequestCodeCache::default(),
+            lancache: RwLock::new(None),
+            chunk_latencies: std::sync::Mutex::new(LatencyWindow::default()),
+            checked_keys: Mutex::new(HashSet::new()),
+            adaptive_limit: config
+                .adaptive_concurrency
+                .map(|(min, max)| AdaptiveLimit::new(config.concurrency, min, max)),
+            config,
         }
     }
 
+    pub async fn licenses(&self) -> Result<Vec<License>, Error> {
+        let mut licenses = self.licenses.clone();
+        let licenses = timeout(LICENSE_LIST_TIMEOUT, licenses.wait_for(Option::is_some))
+            .await
+            .map_err(|_| {
+                Error::Unexpected(
+                    "no license list received, build the client right after logon or supply the licenses"
+                        .to_string(),
+                )
+            })?
+            .map_err(|err| Error::Unexpected(err.to_string()))?;
+        Ok(licenses.clone().unwrap_or_default())
+    }
+
     pub fn cell_id(&self) -> u32 {
-        self.connection.cell_id()
+        self.config
+            .cell_id
+            .unwrap_or_else(|| self.connection.cell_id())
     }
 
-    async fn pick_server(&self) -> Result<CDNServer, Error> {
-        let mut servers = self.servers.lock().await;
-        if servers.is_empty() || servers.iter().all(|(_, penalty)| *penalty > 0) {
-            *servers = web_api::content_service::get_servers_for_steam_pipe(self.cell_id())
-                .await?
-                .into_iter()
-                .map(|s| (s, 0))
-                .collect();
+    /// Keeps the server list current without holding up requests, and saves it
+    /// along with the observed health when a cache directory is configured.
+    pub fn spawn_server_maintenance(inner: &Arc<InnerClient>) {
+        let inner = Arc::downgrade(inner);
+        tokio::spawn(async move {
+            loop {
+                sleep(SERVER_MAINTENANCE_INTERVAL).await;
+                let Some(inner) = inner.upgrade() else {
+                    break;
+                };
+                // failures leave the current list in place until the next round
+                let stale = inner
+                    .servers_fetched
+                    .lock()
+                    .unwrap()
+                    .and_then(|fetched| fetched.elapsed().ok())
+                    .is_some_and(|age| age >= inner.config.server_list_ttl);
+                if stale {
+                    inner.refresh_servers().await.ok();
+                } else {
+                    inner.save_servers().await.ok();
+                }
+            }
+        });
+    }
+
+    fn server_list_path(&self) -> Option<PathBuf> {
+        self.config
+            .cache_dir
+            .as_ref()
+            .map(|cache_dir| cache_dir.join(server_list::SERVER_LIST_FILE))
+    }
+
+    async fn load_servers(&self) -> Result<(), Error> {
+        let _refreshing = self.refreshing_servers.lock().await;
+        if !self.servers.lock().await.is_empty() {
+            return Ok(());
+        }
+
+        let persisted = match self.server_list_path() {
+            Some(path) => {
+                let source = self.server_source.read().unwrap().id();
+                server_list::load(&path, &source, self.cell_id(), self.config.server_list_ttl).await
+            }
+            None => None,
+        };
+        match persisted {
+            Some((fetched, persisted)) => {
+                *self.servers.lock().await = persisted
+                    .into_iter()
+                    .map(|(server, health)| {
+                        ServerEntry::new(server, health, self.config.connections_per_server)
+                    })
+                    .collect();
+                *self.servers_fetched.lock().unwrap() = Some(fetched);
+                Ok(())
+            }
+            None => self.fetch_servers().await,
         }
+    }
+
+    pub async fn refresh_servers(&self) -> Result<(), Error> {
+        let _refreshing = self.refreshing_servers.lock().await;
+        self.fetch_servers().await
+    }
+
+    /// Known servers keep their health and connection limits across refreshes.
+    async fn fetch_servers(&self) -> Result<(), Error> {
+        let server_source = self.server_source.read().unwrap().clone();
+        let fetched = server_source.servers(self.cell_id()).await?;
+
+        let mut servers = self.servers.lock().await;
+        let mut previous = std::mem::take(&mut *servers);
+        *servers = fetched
+            .into_iter()
+            .map(
+                |server| match previous.iter().position(|entry| entry.is(&server)) {
+                    Some(index) => {
+                        let mut entry = previous.swap_remove(index);
+                        entry.server = server;
+                        entry
+                    }
+                    None => ServerEntry::new(
+                        server,
+                        ServerHealth::default(),
+                        self.config.connections_per_server,
+                    ),
+                },
+            )
+            .collect();
+        drop(servers);
 
-        if let Some((server, _)) = servers
+        *self.servers_fetched.lock().unwrap() = Some(SystemTime::now());
+        // persisting is best effort, the list is in use either way
+        self.save_servers().await.ok();
+        Ok(())
+    }
+
+    async fn save_servers(&self) -> Result<(), Error> {
+        let (Some(path), Some(fetched)) = (
+            self.server_list_path(),
+            *self.servers_fetched.lock().unwrap(),
+        ) else {
+            return Ok(());
+        };
+        let servers = self
+            .servers
+            .lock()
+            .await
             .iter()
-            .find(|(server, penalty)| server.cell_id == self.cell_id() && *penalty == 0)
-        {
-            return Ok(server.clone());
+            .map(|entry| (entry.server.clone(), entry.health.snapshot()))
+            .collect::<Vec<_>>();
+        if servers.is_empty() {
+            return Ok(());
+        }
+        let source = self.server_source.read().unwrap().id();
+        server_list::save(&path, source, self.cell_id(), fetched, servers).await
+    }
+
+    /// Spreads requests over the best scored servers, picking the one with the
+    /// fewest requests in flight and waiting when all of them are at their limit.
+    async fn pick_server(
+        &self,
+        exclude: Option<&CDNServer>,
+    ) -> Result<(CDNServer, OwnedSemaphorePermit), Error> {
+        if self.servers.lock().await.is_empty() {
+            self.load_servers().await?;
         }
 
-        servers
+        let servers = self.servers.lock().await;
+        let cell_id = self.cell_id();
+        let candidates = servers
             .iter()
-            .filter(|(s, _)| s.r#type == "SteamCache" || s.r#type == "CDN")
-            .min_by_key(|(s, penalty)| (*penalty, s.weighted_load))
-            .ok_or(Error::Network("no available cdn servers".to_string()))
-            .map(|(server, _)| server.clone())
+            .filter(|entry| entry.server.r#type == "SteamCache" || entry.server.r#type == "CDN")
+            .filter(|entry| exclude.is_none_or(|exclude| !entry.is(exclude)))
+            .map(|entry| (entry.health.score(&entry.server, cell_id), entry))
+            .sorted_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, entry)| entry)
+            .take(self.config.spread_servers)
+            .collect::<Vec<&ServerEntry>>();
+
+        // candidates are sorted best first and ties go to the last maximum, hence `rev`
+        let entry = candidates
+            .iter()
+            .rev()
+            .max_by_key(|entry| entry.connections.available_permits())
+            .filter(|entry| entry.connections.available_permits() > 0)
+            .or(candidates.first())
+            .ok_or(Error::Network("no available cdn servers".to_string()))?;
+        let server = entry.server.clone();
+        let connections = entry.connections.clone();
+        drop(servers);
+
+        Ok((server, connections.acquire_owned().await?))
     }
 
-    async fn server_penalty(&self, server: &CDNServer) {
+    async fn update_health<F: FnOnce(&mut ServerHealth)>(&self, server: &CDNServer, update: F) {
         let mut servers = self.servers.lock().await;
-        if let Some((_, penalty)) = servers.iter_mut().find(|(s, _)| s == server) {
-            *penalty += 1;
+        if let Some(entry) = servers.iter_mut().find(|entry| entry.is(server)) {
+            update(&mut entry.health);
         }
     }
 
     pub async fn get_product_info(
         &self,
         app_ids: Vec<u32>,
-    ) -> Result<CMsgClientPICSProductInfoResponse, Error> {
-        let tokens: CMsgClientPICSAccessTokenResponse = self
-            .connection
-            .job(CMsgClientPICSAccessTokenRequest {
-                appids: app_ids,
-                ..Default::default()
-            })
-            .await?;
+        package_ids: Vec<u32>,
+    ) -> Result<ProductInfo, Error> {
+        let tokens = self.connection.access_tokens(app_ids, package_ids).await?;
+        self.connection.product_info(tokens).await
+    }
 
-        let product_info: CMsgClientPICSProductInfoResponse = self
+    fn cached_cdn_auth_token(&self, depot_id: u32, server: &CDNServer) -> Option<String> {
+        self.cdn_auth_tokens
+            .lock()
+            .unwrap()
+            .get(&(depot_id, server.vhost.clone()))
+            .filter(|(_, expiry)| *expiry > SystemTime::now())
+            .map(|(token, _)| token.clone())
+    }
+
+    async fn refresh_cdn_auth_token(
+        &self,
+        depot_id: u32,
+        server: &CDNServer,
+    ) -> Result<Option<String>, Error> {
+        let Some(token) = self
             .connection
-            .job(CMsgClientPICSProductInfoRequest {
-                apps: tokens
-                    .app_access_tokens
-                    .into_iter()
-                    .map(|app_token| AppInfo {
-                        appid: app_token.appid,
-                        access_token: app_token.access_token,
-                        ..Default::default()
-                    })
-                    .collect::<Vec<AppInfo>>(),
-                meta_data_only: Some(false),
-                ..Default::default()
-            })
-            .await?;
-        Ok(product_info)
+            .cdn_auth_token(depot_id, server.vhost.clone())
+            .await?
+        else {
+            return Ok(None);
+        };
+        self.cdn_auth_tokens.lock().unwrap().insert(
+            (depot_id, server.vhost.clone()),
+            (token.token.clone(), token.expiry),
+        );
+        Ok(Some(token.token))
+    }
+
+    /// Routes through the lan cache when one is set, keeping the server vhost in the
+    /// `Host` header so the cache can tell which upstream the request is meant for.
+    fn get(&self, server: &CDNServer, path: &str, token: Option<String>) -> RequestBuilder {
+        let path = with_token(path, token);
+        match *self.lancache.read().unwrap() {
+            Some(lancache) => {
+                let vhost = if server.vhost.is_empty() {
+                    &server.host
+                } else {
+                    &server.vhost
+                };
+                self.web_client
+                    .get(format!("http://{lancache}{path}"))
+                    .header(HOST, vhost)
+            }
+            None => self.web_client.get(format!(
+                "{}://{}:{}{path}",
+                if server.https { "https" } else { "http" },
+                server.host,
+                server.port,
+            )),
+        }
     }
 
     pub async fn remote_cmd<C: AsRef<str>, A: AsRef<str>>(
         &self,
         command: C,
         args: A,
+        depot_id: Option<u32>,
         manifest_request_code: Option<u64>,
-    ) -> Result<Response, Error> {
-        let server = self.pick_server().await?;
-        let mut url = format!(
-            "{}://{}:{}/{}/{}",
-            if server.https { "https" } else { "http" },
-            server.host,
-            server.port,
-            command.as_ref(),
-            args.as_ref()
-        );
+    ) -> Result<Bytes, Error> {
+        let mut path = format!("/{}/{}", command.as_ref(), args.as_ref());
         if let Some(manifest_request_code) = manifest_request_code {
-            url.push('/');
-            url.push_str(manifest_request_code.to_string().as_str());
+            path.push('/');
+            path.push_str(manifest_request_code.to_string().as_str());
+        }
+        self.config
+            .retry
+            .run(|| async {
+                let (server, _permit) = self.pick_server(None).await?;
+                self.request(&server, &path, depot_id).await
+            })
+            .await
+    }
+
+    async fn request(
+        &self,
+        server: &CDNServer,
+        path: &str,
+        depot_id: Option<u32>,
+    ) -> Result<Bytes, Error> {
+        let result = self.fetch(server, path, depot_id).await;
+        match &result {
+            Ok((bytes, latency, transfer)) => {
+                self.update_health(server, |health| {
+                    health.record_success(*latency, bytes.len(), *transfer)
+                })
+                .await
+            }
+            Err(_) => {
+                self.update_health(server, ServerHealth::record_failure)
+                    .await
+            }
         }
+        result.map(|(bytes, _, _)| bytes)
+    }
 
-        let response = self.web_client.get(url).send().await?;
+    /// Returns the body along with the time to response headers and the body transfer time.
+    async fn fetch(
+        &self,
+        server: &CDNServer,
+        path: &str,
+        depot_id: Option<u32>,
+    ) -> Result<(Bytes, Duration, Duration), Error> {
+        let started = Instant::now();
+        let token = depot_id.and_then(|depot_id| self.cached_cdn_auth_token(depot_id, server));
+        let mut response = self.send(self.get(server, path, token)).await?;
+        // protected servers reject requests without a (valid) token, retry once with a fresh one
+        if let (StatusCode::FORBIDDEN, Some(depot_id)) = (response.status(), depot_id) {
+            if let Some(token) = self.refresh_cdn_auth_token(depot_id, server).await? {
+                response = self.send(self.get(server, path, Some(token))).await?;
+            }
+        }
         if !response.status().is_success() {
-            self.server_penalty(&server).await;
+            return Err(Error::HttpStatus(response.status()));
+        }
+
+        let latency = started.elapsed();
+        let bytes = match self.config.read_timeout {
+            Some(read_timeout) => {
+                let mut body = BytesMut::new();
+                while let Some(chunk) = read_with_timeout(read_timeout, response.chunk()).await? {
+                    body.extend_from_slice(&chunk);
+                }
+                body.freeze()
+            }
+            None => response.bytes().await?,
+        };
+        Ok((bytes, latency, started.elapsed() - latency))
+    }
+
+    async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        match self.config.read_timeout {
+            Some(read_timeout) => read_with_timeout(read_timeout, request.send()).await,
+            None => Ok(request.send().await?),
+        }
+    }
+
+    /// Once a chunk takes longer than most recent ones, a duplicate request goes to
+    /// another server and whichever answers first wins, the other one is dropped.
+    pub async fn get_raw_chunk(&self, depot_id: u32, chunk_id: String) -> Result<Vec<u8>, Error> {
+        let path = format!("/depot/{depot_id}/chunk/{chunk_id}");
+        let hedge_delay = self
+            .chunk_latencies
+            .lock()
+            .unwrap()
+            .percentile(HEDGE_PERCENTILE);
+        let started = Instant::now();
+
+        let (server, _permit) = self.pick_server(None).await?;
+        let mut primary = pin!(self.request(&server, &path, Some(depot_id)));
+        let result = match hedge_delay {
+            Some(hedge_delay) => match timeout(hedge_delay, &mut primary).await {
+                Ok(result) => result,
+                Err(_) => {
+                    let hedge = pin!(async {
+                        let (server, _permit) = self.pick_server(Some(&server)).await?;
+                        self.request(&server, &path, Some(depot_id)).await
+                    });
+                    // a failing request shouldn't win over one that may still succeed
+                    match select(primary, hedge).await {
+                        Either::Left((Ok(bytes), _)) => Ok(bytes),
+                        // the dropped primary never gets to report, its server still has to
+                        // look as slow as it was or it keeps being picked
+                        Either::Right((Ok(bytes), _)) => {
+                            let elapsed = started.elapsed();
+                            self.update_health(&server, |health| health.record_slow(elapsed))
+                                .await;
+                            Ok(bytes)
+                        }
+                        Either::Left((Err(_), hedge)) => hedge.await,
+                        Either::Right((Err(_), primary)) => primary.await,
+                    }
+                }
+            },
+            None => primary.await,
+        };
+
+        let bytes = result?;
+        self.chunk_latencies
+            .lock()
+            .unwrap()
+            .record(started.elapsed());
+        Ok(bytes.to_vec())
+    }
+
+    /// Remembers a key found to match the depot, e.g. by decrypting its filenames.
+    pub async fn key_checked(&self, depot_id: u32, depot_key: [u8; 32]) {
+        self.checked_keys.lock().await.insert((depot_id, depot_key));
+    }
+
+    /// Checks `depot_key` once per depot by decrypting `chunk`, so a wrong key fails
+    /// right away instead of every chunk being retried as if it were corrupted.
+    pub async fn check_depot_key(
+        &self,
+        depot_id: u32,
+        depot_key: [u8; 32],
+        chunk: &ChunkData,
+    ) -> Result<(), Error> {
+        let mut checked_keys = self.checked_keys.lock().await;
+        if checked_keys.contains(&(depot_id, depot_key)) {
+            return Ok(());
         }
 
-        Ok(response)
+        // a sample corrupted in transit shouldn't pass for a wrong key, so it gets a second fetch
+        let mut result = Ok(());
+        for _ in 0..2 {
+            let mut bytes = self
+                .config
+                .retry
+                .run(|| self.get_raw_chunk(depot_id, chunk.id()))
+                .await?;
+            result = depot_chunk::decrypt(&mut bytes[..], depot_key).map(|_| ());
+            if result.is_ok() {
+                checked_keys.insert((depot_id, depot_key));
+                break;
+            }
+        }
+        result
     }
 
+    /// Retries the whole download, a chunk that fails verification is as likely
+    /// to come back intact from another server as one that failed to download.
     pub async fn get_chunk(
         &self,
         depot_id: u32,
         depot_key: [u8; 32],
-        chunk_id: String,
+        chunk: &ChunkData,
     ) -> Result<Vec<u8>, Error> {
-        let response = self
-            .remote_cmd("depot", format!("{depot_id}/chunk/{chunk_id}"), None)
-            .await?;
-        if !response.status().is_success() {
-            return Err(Error::HttpStatus(response.status()));
+        self.config
+            .retry
+            .run(|| async {
+                let started = Instant::now();
+                let result = self.download_chunk(depot_id, depot_key, chunk).await;
+                if let Some(adaptive_limit) = &self.adaptive_limit {
+                    match &result {
+                        Ok(data) => adaptive_limit.record_success(data.len(), started.elapsed()),
+                        Err(_) => adaptive_limit.record_failure(),
+                    }
+                }
+                result
+            })
+            .await
+    }
+
+    async fn download_chunk(
+        &self,
+        depot_id: u32,
+        depot_key: [u8; 32],
+        chunk: &ChunkData,
+    ) -> Result<Vec<u8>, Error> {
+        let mut bytes = self.get_raw_chunk(depot_id, chunk.id()).await?;
+        let data = depot_chunk::decrypt_and_decompress(&mut bytes[..], depot_key).await?;
+        if self.config.verification == Verification::Strict
+            && (data.len() != chunk.original_size() as usize
+                || adler::steam_adler32(&data) != chunk.crc())
+        {
+            return Err(Error::ChunkMismatch(chunk.id()));
         }
+        Ok(data)
+    }
+}
+
+async fn read_with_timeout<T, F: Future<Output = reqwest::Result<T>>>(
+    read_timeout: Duration,
+    future: F,
+) -> Result<T, Error> {
+    Ok(timeout(read_timeout, future)
+        .await
+        .map_err(|_| Error::Request("read timed out".to_string()))??)
+}
 
-        let mut bytes = response.bytes().await?.to_vec();
-        depot_chunk::decrypt_and_decompress(&mut bytes[..], depot_key).await
+fn with_token(url: &str, token: Option<String>) -> String {
+    match token {
+        Some(token) if token.starts_with('?') => format!("{url}{token}"),
+        Some(token) => format!("{url}?{token}"),
+        None => url.to_string(),
     }
 }
@@ -0,0 +1,46 @@
+use std::collections::BTreeSet;
+use steam_vent::proto::steammessages_clientserver::cmsg_client_license_list;
+
+#[derive(Debug, Clone)]
+pub struct License {
+    pub package_id: u32,
+    pub license_type: u32,
+    pub flags: u32,
+    pub time_created: u32,
+    pub owner_id: u32,
+}
+
+impl From<cmsg_client_license_list::License> for License {
+    fn from(license: cmsg_client_license_list::License) -> Self {
+        Self {
+            package_id: license.package_id(),
+            license_type: license.license_type(),
+            flags: license.flags(),
+            time_created: license.time_created(),
+            owner_id: license.owner_id(),
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OwnedContent {
+    pub package_ids: BTreeSet<u32>,
+    pub app_ids: BTreeSet<u32>,
+    pub depot_ids: BTreeSet<u32>,
+}
+
+impl OwnedContent {
+    pub fn owns_app(&self, app_id: u32) -> bool {
+        self.app_ids.contains(&app_id)
+    }
+
+    pub fn owns_depot(&self, depot_id: u32) -> bool {
+        self.depot_ids.contains(&depot_id)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct GrantedLicenses {
+    pub package_ids: Vec<u32>,
+    pub app_ids: Vec<u32>,
+}
@@ -1,9 +1,9 @@
 use futures::{stream::FuturesOrdered, StreamExt};
 use itertools::Itertools;
-use std::{fmt::Write, sync::Arc};
+use std::sync::Arc;
 use tokio::{io::AsyncWriteExt, sync::Semaphore};
 
-use crate::{cdn::inner::InnerClient, Error};
+use crate::{cdn::inner::InnerClient, utils::hex, Error};
 
 #[derive(Debug)]
 pub struct ChunkData {
@@ -20,10 +20,7 @@ impl ChunkData {
     }
 
     pub fn id(&self) -> String {
-        self.sha.iter().fold(String::new(), |mut output, b| {
-            let _ = write!(output, "{b:02x}");
-            output
-        })
+        hex::encode(&self.sha)
     }
 
     pub fn crc(&self) -> u32 {
@@ -104,13 +101,27 @@ impl ManifestFile {
         self.linktarget.clone()
     }
 
+    /// Without `max_tasks`, chunks go through the client's adaptive limit when
+    /// one is configured, or its default concurrency otherwise. A key that doesn't
+    /// match the depot fails with `WrongDepotKey` before any chunk is downloaded.
     pub async fn download<S: AsyncWriteExt + Unpin>(
         &self,
         depot_key: [u8; 32],
         stream: &mut S,
         max_tasks: Option<usize>,
     ) -> Result<(), Error> {
-        let max_tasks = max_tasks.unwrap_or(4);
+        if let Some(chunk) = self.chunks.iter().min_by_key(|chunk| chunk.compressed_size) {
+            self.inner
+                .check_depot_key(self.depot_id, depot_key, chunk)
+                .await?;
+        }
+
+        let adaptive_limit = self
+            .inner
+            .adaptive_limit
+            .as_ref()
+            .filter(|_| max_tasks.is_none());
+        let max_tasks = max_tasks.unwrap_or(self.inner.config.concurrency);
         let semaphore = Arc::new(Semaphore::new(max_tasks));
         let mut tasks = self
             .chunks()
@@ -119,10 +130,17 @@ impl ManifestFile {
             .map(|chunk_data| {
                 let semaphore_owned = semaphore.clone();
                 async move {
+                    if let Some(adaptive_limit) = adaptive_limit {
+                        let _permit = adaptive_limit.acquire().await;
+                        return self
+                            .inner
+                            .get_chunk(self.depot_id, depot_key, chunk_data)
+                            .await;
+                    }
                     let permit = semaphore_owned.acquire_owned().await?;
                     let result = self
                         .inner
-                        .get_chunk(self.depot_id, depot_key, chunk_data.id())
+                        .get_chunk(self.depot_id, depot_key, chunk_data)
                         .await;
                     drop(permit);
                     result
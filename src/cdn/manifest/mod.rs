@@ -1,5 +1,6 @@
+use ::zip::ZipArchive;
 use buf::TryBuf;
-use bytes::Bytes;
+use bytes::{BufMut, Bytes};
 use error::ManifestError;
 use file::{ChunkData, ManifestFile};
 use std::sync::Arc;
@@ -11,10 +12,13 @@ use steam_vent::proto::{
     content_manifest::{ContentManifestMetadata, ContentManifestPayload, ContentManifestSignature},
     protobuf::Message,
 };
-use zip::ZipArchive;
 
 use super::inner::InnerClient;
-use crate::{crypto::aes256, utils::base64::base64_decode};
+use crate::{
+    crypto::aes256,
+    utils::{base64::base64_decode, zip},
+    Error,
+};
 
 mod buf;
 pub mod error;
@@ -80,6 +84,55 @@ impl DepotManifest {
         Ok(())
     }
 
+    /// Checks `key` against an encrypted filename, or a downloaded sample chunk
+    /// when the filenames are already readable.
+    pub async fn check_key(&self, key: [u8; 32]) -> Result<(), Error> {
+        if self.filenames_encrypted {
+            if let Some(file) = self.files.first() {
+                let mut encrypted =
+                    base64_decode(file.filename.as_bytes()).map_err(ManifestError::from)?;
+                let decrypted = aes256::decrypt_cbc_with_iv_extraction(&mut encrypted[..], key)
+                    .map_err(|_| Error::WrongDepotKey)?;
+                str::from_utf8(&decrypted).map_err(|_| Error::WrongDepotKey)?;
+                file.inner.key_checked(self.depot_id, key).await;
+            }
+            return Ok(());
+        }
+
+        let Some((file, chunk)) = self
+            .files
+            .iter()
+            .flat_map(|file| file.chunks.iter().map(move |chunk| (file, chunk)))
+            .min_by_key(|(_, chunk)| chunk.compressed_size)
+        else {
+            return Ok(());
+        };
+        file.inner.check_depot_key(self.depot_id, key, chunk).await
+    }
+
+    /// The inverse of `deserialize`, sections framed by magic and length, then zipped.
+    pub(crate) fn serialize(
+        payload: &ContentManifestPayload,
+        metadata: &ContentManifestMetadata,
+    ) -> Result<Vec<u8>, Error> {
+        let mut buffer = Vec::new();
+        for (magic, section) in [
+            (PROTOBUF_PAYLOAD_MAGIC, payload.write_to_bytes()),
+            (PROTOBUF_METADATA_MAGIC, metadata.write_to_bytes()),
+            (
+                PROTOBUF_SIGNATURE_MAGIC,
+                ContentManifestSignature::new().write_to_bytes(),
+            ),
+        ] {
+            let section = section.map_err(ManifestError::from)?;
+            buffer.put_u32_le(magic);
+            buffer.put_u32_le(section.len() as u32);
+            buffer.extend_from_slice(&section);
+        }
+        buffer.put_u32_le(PROTOBUF_ENDOFMANIFEST_MAGIC);
+        zip::compress("z", &buffer)
+    }
+
     pub(crate) fn deserialize(
         client: Arc<InnerClient>,
         data: &[u8],
@@ -1,25 +1,55 @@
-use depot::AppDepots;
+use app_info::AppInfo;
+use builder::CDNClientBuilder;
+use concurrency::AdaptiveLimit;
+use connection::SteamConnection;
+use depot::{AppDepots, DepotTarget, ResolvedDepot, DEFAULT_BRANCH};
+use depot_key::{DepotKey, DepotKeyProvider};
+use health::ServerHealth;
 use inner::InnerClient;
+use itertools::Itertools;
+use license::{GrantedLicenses, License, OwnedContent};
 use manifest::DepotManifest;
-use std::sync::Arc;
-use steam_vent::{
-    proto::{
-        steammessages_clientserver_2::{
-            CMsgClientGetDepotDecryptionKey, CMsgClientGetDepotDecryptionKeyResponse,
-        },
-        steammessages_contentsystem_steamclient::CContentServerDirectory_GetManifestRequestCode_Request,
-    },
-    Connection, ConnectionTrait,
+use package::PackageInfo;
+use server_source::ServerSource;
+use std::{
+    collections::BTreeMap,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    sync::Arc,
 };
+use tokio::{fs, net::lookup_host};
 
-use crate::Error;
+use crate::{utils::fs as fs_utils, web_api::content_service::CDNServer, Error};
 
+pub mod app_info;
+pub mod builder;
+pub mod concurrency;
+pub mod connection;
 pub mod depot;
+pub mod depot_builder;
 pub mod depot_chunk;
+pub mod depot_key;
+#[cfg(feature = "test-support")]
+pub mod fake;
+pub mod health;
+pub mod http;
 pub mod inner;
+pub mod license;
 pub mod manifest;
+pub mod package;
+pub mod server_list;
+pub mod server_source;
 
 pub const MANIFEST_VERSION: usize = 5;
+const LANCACHE_HOST: &str = "lancache.steamcontent.com";
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_loopback() || ip.is_link_local(),
+        // unique local fc00::/7
+        IpAddr::V6(ip) => ip.is_loopback() || (ip.segments()[0] & 0xfe00) == 0xfc00,
+    }
+}
 
 #[derive(Debug)]
 pub struct CDNClient {
@@ -27,51 +57,209 @@ pub struct CDNClient {
 }
 
 impl CDNClient {
-    pub async fn new(connection: Arc<Connection>) -> Result<Self, Error> {
-        Ok(Self {
-            inner: Arc::new(InnerClient::new(connection)),
-        })
+    /// Takes a steam-vent `Connection` or any other [`SteamConnection`], such as
+    /// `FakeConnection` from the `test-support` feature in tests.
+    pub async fn new<C: SteamConnection + 'static>(connection: Arc<C>) -> Result<Self, Error> {
+        Self::builder(connection).build().await
+    }
+
+    pub fn builder<C: SteamConnection + 'static>(connection: Arc<C>) -> CDNClientBuilder {
+        CDNClientBuilder::new(connection)
     }
 
     // tbd: should be renamed
     pub async fn get_depots(&self, app_ids: Vec<u32>) -> Result<Vec<AppDepots>, Error> {
-        let product_info = self.inner.get_product_info(app_ids).await?;
+        let product_info = self.inner.get_product_info(app_ids, Vec::new()).await?;
         let mut apps_depots: Vec<AppDepots> = Vec::new();
 
-        for app in product_info.apps {
-            let mut app_depots = AppDepots::new(app.appid());
-            app_depots.vdf_parse(app.buffer())?;
+        for (app_id, buffer) in product_info.apps {
+            let mut app_depots = AppDepots::new(app_id);
+            app_depots.vdf_parse(&buffer)?;
             apps_depots.push(app_depots);
         }
 
         Ok(apps_depots)
     }
 
+    pub async fn get_app_info(&self, app_ids: Vec<u32>) -> Result<Vec<AppInfo>, Error> {
+        let product_info = self.inner.get_product_info(app_ids, Vec::new()).await?;
+        let mut apps: Vec<AppInfo> = Vec::new();
+
+        for (app_id, buffer) in product_info.apps {
+            let mut app_info = AppInfo::new(app_id);
+            app_info.vdf_parse(&buffer)?;
+            apps.push(app_info);
+        }
+
+        Ok(apps)
+    }
+
+    pub async fn get_package_info(&self, package_ids: Vec<u32>) -> Result<Vec<PackageInfo>, Error> {
+        let product_info = self.inner.get_product_info(Vec::new(), package_ids).await?;
+        let mut packages: Vec<PackageInfo> = Vec::new();
+
+        for (package_id, buffer) in product_info.packages {
+            let mut package_info = PackageInfo::new(package_id);
+            package_info.vdf_parse(&buffer)?;
+            packages.push(package_info);
+        }
+
+        Ok(packages)
+    }
+
+    /// Licenses of the logged in account, as last pushed by steam. Steam pushes them
+    /// right after logon only, so a client built later has to be given them through
+    /// [`CDNClientBuilder::licenses`] or [`CDNClientBuilder::license_lists`], or this
+    /// fails after a timeout until the next push.
+    pub async fn get_licenses(&self) -> Result<Vec<License>, Error> {
+        self.inner.licenses().await
+    }
+
+    /// Resolves every licensed package through PICS into the apps and depots it grants.
+    pub async fn get_owned_content(&self) -> Result<OwnedContent, Error> {
+        let package_ids = self
+            .get_licenses()
+            .await?
+            .into_iter()
+            .map(|license| license.package_id)
+            .unique()
+            .collect::<Vec<u32>>();
+
+        let mut owned = OwnedContent::default();
+        for package in self.get_package_info(package_ids).await? {
+            owned.package_ids.insert(package.package_id);
+            owned.app_ids.extend(package.app_ids);
+            owned.depot_ids.extend(package.depot_ids);
+        }
+        Ok(owned)
+    }
+
+    /// Requests free licenses for `app_ids`, apps that aren't free to play are
+    /// silently left out of the granted lists.
+    pub async fn request_free_license(&self, app_ids: Vec<u32>) -> Result<GrantedLicenses, Error> {
+        self.inner.connection.request_free_license(app_ids).await
+    }
+
+    /// Resolves the depots to install for `app_id`, following `depotfromapp`
+    /// references so shared depots come back with the owning app id and manifest.
+    pub async fn resolve_depots(
+        &self,
+        app_id: u32,
+        target: &DepotTarget,
+    ) -> Result<Vec<ResolvedDepot>, Error> {
+        let app_depots = self
+            .get_depots(vec![app_id])
+            .await?
+            .into_iter()
+            .find(|app| app.app_id == app_id)
+            .ok_or(Error::Unexpected(format!(
+                "no product info for app {app_id}"
+            )))?;
+
+        let resolved = app_depots.resolve(target, &[]);
+        let shared_app_ids = resolved
+            .iter()
+            .filter(|depot| depot.manifest_id.is_none())
+            .map(|depot| depot.app_id)
+            .unique()
+            .collect::<Vec<u32>>();
+        if shared_app_ids.is_empty() {
+            return Ok(resolved);
+        }
+
+        let shared = self.get_depots(shared_app_ids).await?;
+        Ok(app_depots.resolve(target, &shared))
+    }
+
+    /// Replaces where content servers are discovered, dropping the current list.
+    /// A persisted list is only reused if it came from the same source.
+    pub async fn set_server_source<S: ServerSource + 'static>(&self, source: S) {
+        *self.inner.server_source.write().unwrap() = Arc::new(source);
+        self.inner.servers.lock().await.clear();
+    }
+
+    /// Fetches the server list again right away instead of waiting for it to expire.
+    pub async fn refresh_servers(&self) -> Result<(), Error> {
+        self.inner.refresh_servers().await
+    }
+
+    /// Current chunk download limit when adaptive concurrency is enabled.
+    pub fn concurrency_limit(&self) -> Option<usize> {
+        self.inner.adaptive_limit.as_ref().map(AdaptiveLimit::limit)
+    }
+
+    /// Known content servers along with their observed health.
+    pub async fn server_health(&self) -> Vec<(CDNServer, ServerHealth)> {
+        self.inner
+            .servers
+            .lock()
+            .await
+            .iter()
+            .map(|entry| (entry.server.clone(), entry.health.clone()))
+            .collect()
+    }
+
+    /// Sends every content request to a lan cache (e.g. Lancache) at `addr` over
+    /// plain http, `None` goes back to contacting the servers directly.
+    pub fn set_lancache(&self, addr: Option<SocketAddr>) {
+        *self.inner.lancache.write().unwrap() = addr;
+    }
+
+    /// Lan caches override `lancache.steamcontent.com` in local DNS to point at
+    /// themselves, so a private address there means one is in use. A cache found
+    /// is used from then on, finding none leaves the current setting alone.
+    pub async fn detect_lancache(&self) -> Option<SocketAddr> {
+        // an unresolvable name just means there's no override
+        let addr = lookup_host((LANCACHE_HOST, 80))
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.find(|addr| is_private(addr.ip())));
+        if addr.is_some() {
+            self.set_lancache(addr);
+        }
+        addr
+    }
+
+    /// Adds a key source consulted ahead of the CM, in insertion order.
+    pub fn add_key_provider<P: DepotKeyProvider + 'static>(&self, provider: P) {
+        self.inner
+            .key_providers
+            .write()
+            .unwrap()
+            .push(Arc::new(provider));
+    }
+
     pub async fn get_depot_decryption_key(
         &self,
         app_id: u32,
         depot_id: u32,
-    ) -> Result<Option<[u8; 32]>, Error> {
-        let response: CMsgClientGetDepotDecryptionKeyResponse = self
-            .inner
-            .connection
-            .job(CMsgClientGetDepotDecryptionKey {
-                depot_id: Some(depot_id),
-                app_id: Some(app_id),
-                ..Default::default()
-            })
-            .await?;
-        match response.depot_encryption_key {
-            Some(bytes) if bytes.len() == 32 => {
-                let mut key = [0u8; 32];
-                key.copy_from_slice(&bytes[..]);
-                Ok(Some(key))
+    ) -> Result<Option<DepotKey>, Error> {
+        let providers = self.inner.key_providers.read().unwrap().clone();
+        let mut key = None;
+        for provider in providers {
+            key = provider.depot_key(app_id, depot_id).await?;
+            if key.is_some() {
+                break;
             }
-            Some(_) => Err(Error::Unexpected(
-                "depot key has unexpected size".to_string(),
-            )),
-            None => Ok(None),
         }
+        if key.is_none() {
+            key = self.inner.cm_keys.depot_key(app_id, depot_id).await?;
+        }
+
+        if let Some(key) = key {
+            self.inner.session_keys.insert(depot_id, key);
+        }
+        Ok(key)
+    }
+
+    /// Every depot key handed out during this session, from any provider.
+    pub fn session_depot_keys(&self) -> BTreeMap<u32, DepotKey> {
+        self.inner.session_keys.keys()
+    }
+
+    /// Writes the session keys as `depot_id;hexkey` lines, see [`FileKeyProvider`].
+    pub async fn export_depot_keys<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        depot_key::write_key_file(path.as_ref(), &self.session_depot_keys()).await
     }
 
     pub async fn get_manifest_request_code(
@@ -80,21 +268,50 @@ impl CDNClient {
         depot_id: u32,
         manifest_id: u64,
     ) -> Result<u64, Error> {
-        self.inner
+        self.get_branch_manifest_request_code(app_id, depot_id, manifest_id, None, None)
+            .await
+    }
+
+    /// Request codes are cached per branch for a few minutes, so repeated
+    /// fetches of the same manifest don't go through the CM every time.
+    pub async fn get_branch_manifest_request_code(
+        &self,
+        app_id: u32,
+        depot_id: u32,
+        manifest_id: u64,
+        branch: Option<&str>,
+        branch_password_hash: Option<&str>,
+    ) -> Result<u64, Error> {
+        let key = (
+            app_id,
+            depot_id,
+            manifest_id,
+            branch.unwrap_or(DEFAULT_BRANCH).to_string(),
+        );
+        if let Some(code) = self.inner.request_codes.get(&key) {
+            return Ok(code);
+        }
+
+        let code = self
+            .inner
             .connection
-            .service_method(CContentServerDirectory_GetManifestRequestCode_Request {
-                app_id: Some(app_id),
-                depot_id: Some(depot_id),
-                manifest_id: Some(manifest_id),
-                ..Default::default()
-            })
-            .await?
-            .manifest_request_code
-            .ok_or(Error::Unexpected(
-                "failed to get manifest request code".to_string(),
-            ))
+            .manifest_request_code(
+                app_id,
+                depot_id,
+                manifest_id,
+                branch.map(str::to_string),
+                branch_password_hash.map(str::to_string),
+            )
+            .await?;
+        self.inner.request_codes.insert(key, code);
+        Ok(code)
     }
 
+    /// With a cache directory configured, manifests are read from there first and
+    /// stored after download, as served so they can be parsed again the same way.
+    ///
+    /// Without a depot key, encrypted filenames are left as is and
+    /// [`DepotManifest::filenames_encrypted`] stays `true`.
     pub async fn get_manifest(
         &self,
         depot_id: u32,
@@ -102,22 +319,63 @@ impl CDNClient {
         request_code: Option<u64>,
         depot_key: Option<[u8; 32]>,
     ) -> Result<DepotManifest, Error> {
+        let cache_path = self
+            .inner
+            .config
+            .cache_dir
+            .as_ref()
+            .map(|cache_dir| cache_dir.join(format!("{depot_id}_{manifest_id}.manifest")));
+        // the cache is only an optimisation, any trouble with it counts as a miss
+        if let Some(cache_path) = &cache_path {
+            if let Ok(bytes) = fs::read(cache_path).await {
+                match DepotManifest::deserialize(self.inner.clone(), &bytes) {
+                    Ok(manifest) => return self.finish_manifest(manifest, depot_key).await,
+                    // e.g. a partial write, fetched again below
+                    Err(_) => {
+                        fs::remove_file(cache_path).await.ok();
+                    }
+                }
+            }
+        }
+
         let bytes = self
             .inner
             .remote_cmd(
                 "depot",
-                format!("{depot_id}/manifest/{manifest_id}/{MANIFEST_VERSION}"),
+                format!(
+                    "{depot_id}/manifest/{manifest_id}/{}",
+                    self.inner.config.manifest_version
+                ),
+                Some(depot_id),
                 request_code,
             )
-            .await?
-            .bytes()
             .await?;
+        let manifest = DepotManifest::deserialize(self.inner.clone(), &bytes)?;
+        if let Some(cache_path) = &cache_path {
+            fs_utils::write_atomic(cache_path, &bytes).await.ok();
+        }
+        self.finish_manifest(manifest, depot_key).await
+    }
 
-        let mut manifest = DepotManifest::deserialize(self.inner.clone(), &bytes[..])?;
-        if manifest.filenames_encrypted() {
-            if let Some(key) = depot_key {
-                manifest.decrypt_filenames(key)?;
-            }
+    /// Reads a manifest as served by the CDN, e.g. one from a [`DepotBuilder`](depot_builder::DepotBuilder).
+    /// A supplied key is checked and used to decrypt the filenames.
+    pub async fn parse_manifest(
+        &self,
+        bytes: &[u8],
+        depot_key: Option<[u8; 32]>,
+    ) -> Result<DepotManifest, Error> {
+        let manifest = DepotManifest::deserialize(self.inner.clone(), bytes)?;
+        self.finish_manifest(manifest, depot_key).await
+    }
+
+    async fn finish_manifest(
+        &self,
+        mut manifest: DepotManifest,
+        depot_key: Option<[u8; 32]>,
+    ) -> Result<DepotManifest, Error> {
+        if let (true, Some(key)) = (manifest.filenames_encrypted(), depot_key) {
+            manifest.check_key(key).await?;
+            manifest.decrypt_filenames(key)?;
         }
 
         Ok(manifest)
@@ -0,0 +1,65 @@
+use bytes::Buf;
+use keyvalues_parser::Obj;
+
+use crate::{
+    error::Error,
+    utils::{binary_vdf, vdf},
+};
+
+#[derive(Debug)]
+pub struct PackageInfo {
+    pub package_id: u32,
+    pub billing_type: u32,
+    pub license_type: u32,
+    pub status: u32,
+    pub app_ids: Vec<u32>,
+    pub depot_ids: Vec<u32>,
+}
+
+impl PackageInfo {
+    pub fn new(package_id: u32) -> Self {
+        Self {
+            package_id,
+            billing_type: 0,
+            license_type: 0,
+            status: 0,
+            app_ids: Vec::new(),
+            depot_ids: Vec::new(),
+        }
+    }
+
+    pub fn vdf_parse(&mut self, mut buffer: &[u8]) -> Result<(), Error> {
+        // the steam client expects a leading u32 of 1 ahead of the binary kv
+        if buffer.remaining() < 4 {
+            return Err(Error::Eof("package info buffer".to_string()));
+        }
+        buffer.advance(4);
+
+        let kv = binary_vdf::parse(buffer)?;
+        let package = kv.value.get_obj().ok_or(Error::Unexpected(
+            "failed to get package info value".to_string(),
+        ))?;
+        let number = |key: &str| {
+            vdf::get_str(package, key)
+                .and_then(|value| value.parse().ok())
+                .unwrap_or_default()
+        };
+        self.billing_type = number("billingtype");
+        self.license_type = number("licensetype");
+        self.status = number("status");
+        self.app_ids = id_list(package, "appids");
+        self.depot_ids = id_list(package, "depotids");
+        Ok(())
+    }
+}
+
+fn id_list(obj: &Obj<'_>, key: &str) -> Vec<u32> {
+    vdf::get_obj(obj, key)
+        .map(|list| {
+            list.values()
+                .flatten()
+                .filter_map(|value| value.get_str()?.parse().ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
@@ -0,0 +1,85 @@
+use std::{
+    path::Path,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::fs;
+
+use crate::{utils::fs as fs_utils, web_api::content_service::CDNServer, Error};
+
+use super::health::{HealthSnapshot, ServerHealth};
+
+pub(crate) const SERVER_LIST_FILE: &str = "servers.json";
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ServerListFile {
+    source: String,
+    cell_id: u32,
+    fetched: u64,
+    saved: u64,
+    servers: Vec<PersistedServer>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedServer {
+    server: CDNServer,
+    health: HealthSnapshot,
+}
+
+/// A list from another source or cell, past `ttl` or unreadable is as good as none.
+pub(crate) async fn load(
+    path: &Path,
+    source: &str,
+    cell_id: u32,
+    ttl: Duration,
+) -> Option<(SystemTime, Vec<(CDNServer, ServerHealth)>)> {
+    let content = fs::read(path).await.ok()?;
+    let file: ServerListFile = serde_json::from_slice(&content).ok()?;
+    let fetched = UNIX_EPOCH + Duration::from_secs(file.fetched);
+    let age = fetched.elapsed().ok()?;
+    if file.source != source || file.cell_id != cell_id || age >= ttl || file.servers.is_empty() {
+        return None;
+    }
+
+    // health kept decaying while the list sat on disk
+    let saved_age = (UNIX_EPOCH + Duration::from_secs(file.saved))
+        .elapsed()
+        .unwrap_or_default();
+    let servers = file
+        .servers
+        .into_iter()
+        .map(|persisted| {
+            let health = ServerHealth::restore(persisted.health, saved_age);
+            (persisted.server, health)
+        })
+        .collect();
+    Some((fetched, servers))
+}
+
+pub(crate) async fn save(
+    path: &Path,
+    source: String,
+    cell_id: u32,
+    fetched: SystemTime,
+    servers: Vec<(CDNServer, HealthSnapshot)>,
+) -> Result<(), Error> {
+    let file = ServerListFile {
+        source,
+        cell_id,
+        fetched: unix_secs(fetched),
+        saved: unix_secs(SystemTime::now()),
+        servers: servers
+            .into_iter()
+            .map(|(server, health)| PersistedServer { server, health })
+            .collect(),
+    };
+    let content =
+        serde_json::to_vec_pretty(&file).map_err(|err| Error::Unexpected(err.to_string()))?;
+    fs_utils::write_atomic(path, content).await?;
+    Ok(())
+}
+
+fn unix_secs(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
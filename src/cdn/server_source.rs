@@ -0,0 +1,113 @@
+use futures::future::BoxFuture;
+use itertools::Itertools;
+use reqwest::Client;
+use std::{fmt::Debug, sync::Arc};
+use steam_vent::proto::steammessages_contentsystem_steamclient::CContentServerDirectory_ServerInfo;
+
+use crate::{
+    web_api::{self, content_service::CDNServer},
+    Error,
+};
+
+use super::connection::SteamConnection;
+
+/// Where the list of content servers to download from comes from.
+pub trait ServerSource: Debug + Send + Sync {
+    fn servers(&self, cell_id: u32) -> BoxFuture<'_, Result<Vec<CDNServer>, Error>>;
+
+    /// Identifies the source a persisted list came from, so a list is only
+    /// reused by a client configured with the same source.
+    fn id(&self) -> String;
+}
+
+/// `IContentServerDirectoryService/GetServersForSteamPipe` on api.steampowered.com.
+#[derive(Debug, Default)]
+pub struct WebApiServerSource {
+    client: Client,
+}
+
+impl WebApiServerSource {
+    pub fn new(client: Client) -> Self {
+        Self { client }
+    }
+}
+
+impl ServerSource for WebApiServerSource {
+    fn servers(&self, cell_id: u32) -> BoxFuture<'_, Result<Vec<CDNServer>, Error>> {
+        Box::pin(web_api::content_service::get_servers_for_steam_pipe(
+            &self.client,
+            cell_id,
+        ))
+    }
+
+    fn id(&self) -> String {
+        "webapi".to_string()
+    }
+}
+
+/// The same directory queried through the connected CM, for networks without web api access.
+#[derive(Debug)]
+pub struct CMServerSource {
+    connection: Arc<dyn SteamConnection>,
+}
+
+impl CMServerSource {
+    pub fn new(connection: Arc<dyn SteamConnection>) -> Self {
+        Self { connection }
+    }
+}
+
+impl ServerSource for CMServerSource {
+    fn servers(&self, cell_id: u32) -> BoxFuture<'_, Result<Vec<CDNServer>, Error>> {
+        self.connection.servers(cell_id)
+    }
+
+    fn id(&self) -> String {
+        "cm".to_string()
+    }
+}
+
+/// A fixed list, e.g. a local mirror.
+#[derive(Debug, Clone)]
+pub struct StaticServerSource {
+    servers: Vec<CDNServer>,
+}
+
+impl StaticServerSource {
+    pub fn new(servers: Vec<CDNServer>) -> Self {
+        Self { servers }
+    }
+}
+
+impl ServerSource for StaticServerSource {
+    fn servers(&self, _cell_id: u32) -> BoxFuture<'_, Result<Vec<CDNServer>, Error>> {
+        let servers = self.servers.clone();
+        Box::pin(async move { Ok(servers) })
+    }
+
+    fn id(&self) -> String {
+        let servers = self
+            .servers
+            .iter()
+            .map(|server| format!("{}:{}/{}", server.host, server.port, server.vhost))
+            .join(",");
+        format!("static:{servers}")
+    }
+}
+
+impl From<CContentServerDirectory_ServerInfo> for CDNServer {
+    fn from(server: CContentServerDirectory_ServerInfo) -> Self {
+        let https = server.https_support() == "mandatory";
+        CDNServer {
+            r#type: server.type_().to_string(),
+            https,
+            host: server.host().to_string(),
+            vhost: server.vhost().to_string(),
+            port: if https { 443 } else { 80 },
+            cell_id: server.cell_id() as u32,
+            load: server.load() as u32,
+            weighted_load: server.weighted_load() as u32,
+            priority_class: server.priority_class(),
+        }
+    }
+}
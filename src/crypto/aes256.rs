@@ -2,9 +2,9 @@ use aes::{
     cipher::{
         block_padding::{Pkcs7, UnpadError},
         generic_array::GenericArray,
-        BlockDecrypt, BlockDecryptMut, KeyInit, KeyIvInit,
+        BlockDecrypt, BlockDecryptMut, BlockEncrypt, BlockEncryptMut, KeyInit, KeyIvInit,
     },
-    Aes256, Aes256Dec,
+    Aes256, Aes256Dec, Aes256Enc,
 };
 
 pub const IV_LENGTH: usize = 16;
@@ -25,3 +25,21 @@ pub fn decrypt_cbc_with_iv_extraction(
     .decrypt_padded_mut::<Pkcs7>(&mut data[IV_LENGTH..])?
     .to_vec())
 }
+
+/// Counterpart of `decrypt_cbc_with_iv_extraction`, the iv goes in front encrypted with ECB.
+pub fn encrypt_cbc_with_iv(data: &[u8], key: [u8; 32], iv: [u8; IV_LENGTH]) -> Vec<u8> {
+    let mut encrypted = vec![0u8; IV_LENGTH + (data.len() / IV_LENGTH + 1) * IV_LENGTH];
+    encrypted[..IV_LENGTH].copy_from_slice(&iv);
+    Aes256Enc::new(GenericArray::from_slice(&key))
+        .encrypt_block(GenericArray::from_mut_slice(&mut encrypted[..IV_LENGTH]));
+
+    let body = &mut encrypted[IV_LENGTH..];
+    body[..data.len()].copy_from_slice(data);
+    cbc::Encryptor::<Aes256>::new(
+        GenericArray::from_slice(&key),
+        GenericArray::from_slice(&iv),
+    )
+    .encrypt_padded_mut::<Pkcs7>(body, data.len())
+    .expect("buffer has room for a full padding block");
+    encrypted
+}
@@ -1,7 +1,7 @@
 use aes::cipher::block_padding::UnpadError;
 use lzma_rs::error::Error as LzmaError;
 use reqwest::StatusCode;
-use steam_vent::NetworkError;
+use steam_vent::{EResult, NetworkError};
 use tokio::{sync::AcquireError, task::JoinError};
 use zip::result::ZipError;
 
@@ -23,12 +23,18 @@ pub enum Error {
     HttpStatus(StatusCode),
     #[error("{0}")]
     Network(String),
+    #[error("steam result - {0:?}")]
+    EResult(EResult),
     #[error("malformed vdf - {0}")]
     InvalidVDF(String),
     #[error("manifest {0}")]
     Manifest(#[from] ManifestError),
     #[error("unexpected none")]
     NoneOption,
+    #[error("wrong depot key")]
+    WrongDepotKey,
+    #[error("chunk {0} doesn't match its manifest checksum")]
+    ChunkMismatch(String),
 }
 
 impl From<JoinError> for Error {
@@ -55,9 +61,52 @@ impl From<reqwest::Error> for Error {
     }
 }
 
+impl Error {
+    pub fn eresult(&self) -> Option<EResult> {
+        match self {
+            Self::EResult(eresult) => Some(*eresult),
+            _ => None,
+        }
+    }
+
+    /// Whether the same call may succeed later, as opposed to e.g. missing ownership.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            Self::EResult(eresult) => matches!(
+                eresult,
+                EResult::Fail
+                    | EResult::NoConnection
+                    | EResult::Busy
+                    | EResult::Timeout
+                    | EResult::ServiceUnavailable
+                    | EResult::TryAnotherCM
+                    | EResult::RateLimitExceeded
+                    | EResult::RemoteCallFailed
+                    | EResult::IOFailure
+                    | EResult::RemoteDisconnect
+            ),
+            Self::Network(_) | Self::Request(_) | Self::Decompress(_) | Self::ChunkMismatch(_) => {
+                true
+            }
+            Self::HttpStatus(status) => status.is_server_error(),
+            _ => false,
+        }
+    }
+}
+
+impl From<EResult> for Error {
+    fn from(eresult: EResult) -> Self {
+        Self::EResult(eresult)
+    }
+}
+
 impl From<NetworkError> for Error {
     fn from(err: NetworkError) -> Self {
-        Self::Network(err.to_string())
+        match err {
+            NetworkError::ApiError(eresult) => Self::EResult(eresult),
+            NetworkError::Timeout => Self::EResult(EResult::Timeout),
+            err => Self::Network(err.to_string()),
+        }
     }
 }
 
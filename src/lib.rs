@@ -9,5 +9,25 @@ mod error;
 mod utils;
 mod web_api;
 
-pub use cdn::CDNClient;
+#[cfg(feature = "test-support")]
+pub mod test_support;
+
+#[cfg(feature = "test-support")]
+pub use cdn::fake::{FakeCall, FakeConnection};
+pub use cdn::{
+    app_info::{AppCommon, AppConfig, AppIcons, AppInfo, LaunchEntry, ResolvedLaunch},
+    builder::{CDNClientBuilder, RetryPolicy, Verification},
+    connection::{AccessTokens, CDNAuthToken, ProductInfo, SteamConnection},
+    depot::{AppDepots, DepotTarget, ResolvedDepot},
+    depot_builder::{BuiltDepot, ChunkCompression, DepotBuilder},
+    depot_key::{CMKeyProvider, DepotKey, DepotKeyProvider, FileKeyProvider, MemoryKeyProvider},
+    health::ServerHealth,
+    http::HttpOptions,
+    license::{GrantedLicenses, License, OwnedContent},
+    package::PackageInfo,
+    server_source::{CMServerSource, ServerSource, StaticServerSource, WebApiServerSource},
+    CDNClient,
+};
 pub use error::Error;
+pub use steam_vent::EResult;
+pub use web_api::content_service::CDNServer;
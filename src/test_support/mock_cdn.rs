@@ -0,0 +1,245 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    task::JoinHandle,
+    time::sleep,
+};
+
+use crate::{web_api::content_service::CDNServer, BuiltDepot, Error};
+
+const MAX_REQUEST_HEAD: usize = 16 * 1024;
+
+/// What goes wrong with a response.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Fault {
+    /// Answers with this status and an empty body, e.g. 404 or 503.
+    Status(u16),
+    /// Flips bits in the body.
+    Corrupt,
+    /// Announces the full length but closes after half of the body.
+    Truncate,
+    /// Waits before answering.
+    Delay(Duration),
+}
+
+#[derive(Debug, Default)]
+struct MockState {
+    manifests: BTreeMap<(u32, u64), Vec<u8>>,
+    chunks: BTreeMap<(u32, String), Vec<u8>>,
+    latency: Duration,
+    next_faults: VecDeque<Fault>,
+    fault: Option<Fault>,
+    requests: Vec<String>,
+}
+
+/// Local plain http content server answering `depot/{id}/manifest/{gid}/{version}/{code}`
+/// and `depot/{id}/chunk/{sha}` from what was added to it. Stops when dropped.
+#[derive(Debug)]
+pub struct MockCdn {
+    addr: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    listener: JoinHandle<()>,
+}
+
+impl Drop for MockCdn {
+    fn drop(&mut self) {
+        self.listener.abort();
+    }
+}
+
+impl MockCdn {
+    pub async fn start() -> Result<Self, Error> {
+        let listener = TcpListener::bind(("127.0.0.1", 0)).await?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+        let listener_state = state.clone();
+        let listener = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, listener_state.clone()));
+            }
+        });
+        Ok(Self {
+            addr,
+            state,
+            listener,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The server as `CDNClient` sees it, e.g. for a `StaticServerSource`.
+    pub fn server(&self) -> CDNServer {
+        CDNServer::new(self.addr.ip().to_string(), self.addr.port(), false)
+    }
+
+    /// Served as is, whatever the manifest version and request code asked for.
+    pub fn add_manifest(&self, depot_id: u32, manifest_id: u64, body: Vec<u8>) {
+        self.state().manifests.insert((depot_id, manifest_id), body);
+    }
+
+    /// `chunk_id` is the hex encoded chunk sha, `body` the encrypted chunk.
+    pub fn add_chunk<I: Into<String>>(&self, depot_id: u32, chunk_id: I, body: Vec<u8>) {
+        self.state()
+            .chunks
+            .insert((depot_id, chunk_id.into().to_lowercase()), body);
+    }
+
+    /// Serves the manifest and every chunk of `depot`.
+    pub fn add_depot(&self, depot: &BuiltDepot) {
+        self.add_manifest(depot.depot_id, depot.manifest_gid, depot.manifest.clone());
+        for (chunk_id, chunk) in &depot.chunks {
+            self.add_chunk(depot.depot_id, chunk_id.clone(), chunk.clone());
+        }
+    }
+
+    /// Added to every response.
+    pub fn set_latency(&self, latency: Duration) {
+        self.state().latency = latency;
+    }
+
+    /// Applied to the next request only, queued faults are used up in order.
+    pub fn push_fault(&self, fault: Fault) {
+        self.state().next_faults.push_back(fault);
+    }
+
+    /// Applied to every request once the queued faults are used up.
+    pub fn set_fault(&self, fault: Option<Fault>) {
+        self.state().fault = fault;
+    }
+
+    /// Paths requested so far, in order.
+    pub fn requests(&self) -> Vec<String> {
+        self.state().requests.clone()
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, MockState> {
+        self.state.lock().unwrap()
+    }
+}
+
+async fn serve(mut stream: TcpStream, state: Arc<Mutex<MockState>>) {
+    let Some(path) = read_request_path(&mut stream).await else {
+        return;
+    };
+    let (body, latency, fault) = {
+        let mut state = state.lock().unwrap();
+        state.requests.push(path.clone());
+        let fault = state.next_faults.pop_front().or(state.fault.clone());
+        (route(&state, &path), state.latency, fault)
+    };
+
+    sleep(latency).await;
+    let response = match (body, fault) {
+        (_, Some(Fault::Status(status))) => Response::status(status),
+        (None, _) => Response::status(404),
+        (Some(body), Some(Fault::Delay(delay))) => {
+            sleep(delay).await;
+            Response::ok(body)
+        }
+        (Some(mut body), Some(Fault::Corrupt)) => {
+            for byte in body.iter_mut().step_by(7) {
+                *byte ^= 0x5a;
+            }
+            Response::ok(body)
+        }
+        (Some(body), Some(Fault::Truncate)) => Response {
+            status: 200,
+            length: body.len(),
+            body: body[..body.len() / 2].to_vec(),
+        },
+        (Some(body), None) => Response::ok(body),
+    };
+    // the client going away mid response is fine
+    response.write(&mut stream).await.ok();
+}
+
+fn route(state: &MockState, path: &str) -> Option<Vec<u8>> {
+    let path = path.split('?').next()?;
+    let segments = path
+        .trim_start_matches('/')
+        .split('/')
+        .collect::<Vec<&str>>();
+    match segments.as_slice() {
+        ["depot", depot_id, "manifest", manifest_id, ..] => state
+            .manifests
+            .get(&(depot_id.parse().ok()?, manifest_id.parse().ok()?))
+            .cloned(),
+        ["depot", depot_id, "chunk", chunk_id] => state
+            .chunks
+            .get(&(depot_id.parse().ok()?, chunk_id.to_lowercase()))
+            .cloned(),
+        _ => None,
+    }
+}
+
+async fn read_request_path(stream: &mut TcpStream) -> Option<String> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        let read = stream.read(&mut buffer).await.ok()?;
+        if read == 0 || head.len() > MAX_REQUEST_HEAD {
+            return None;
+        }
+        head.extend_from_slice(&buffer[..read]);
+    }
+    let head = String::from_utf8_lossy(&head);
+    let mut request_line = head.lines().next()?.split_whitespace();
+    match (request_line.next()?, request_line.next()?) {
+        ("GET", path) => Some(path.to_string()),
+        _ => None,
+    }
+}
+
+struct Response {
+    status: u16,
+    length: usize,
+    body: Vec<u8>,
+}
+
+impl Response {
+    fn ok(body: Vec<u8>) -> Self {
+        Self {
+            status: 200,
+            length: body.len(),
+            body,
+        }
+    }
+
+    fn status(status: u16) -> Self {
+        Self {
+            status,
+            length: 0,
+            body: Vec::new(),
+        }
+    }
+
+    async fn write(self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let head = format!(
+            "HTTP/1.1 {} {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+            self.status,
+            reason(self.status),
+            self.length
+        );
+        stream.write_all(head.as_bytes()).await?;
+        stream.write_all(&self.body).await?;
+        stream.shutdown().await
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        403 => "Forbidden",
+        404 => "Not Found",
+        503 => "Service Unavailable",
+        _ => "Error",
+    }
+}
@@ -0,0 +1,16 @@
+const MOD_ADLER: u32 = 65521;
+
+/// Adler-32 as steam computes chunk checksums, seeded with 0 instead of 1.
+pub fn steam_adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (0u32, 0u32);
+    // 5552 is the largest run that can't overflow before reducing
+    for block in data.chunks(5552) {
+        for byte in block {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= MOD_ADLER;
+        b %= MOD_ADLER;
+    }
+    a | (b << 16)
+}
@@ -1,6 +1,6 @@
 use base64::{
     alphabet,
-    engine::{DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
+    engine::{general_purpose::STANDARD, DecodePaddingMode, GeneralPurpose, GeneralPurposeConfig},
     DecodeError, Engine,
 };
 
@@ -20,3 +20,7 @@ pub fn base64_decode<T: AsRef<[u8]>>(input: T) -> Result<Vec<u8>, DecodeError> {
     .decode_vec(filtered, &mut decoded)?;
     Ok(decoded)
 }
+
+pub fn base64_encode<T: AsRef<[u8]>>(input: T) -> String {
+    STANDARD.encode(input)
+}
@@ -0,0 +1,85 @@
+use bytes::Buf;
+use keyvalues_parser::{Obj, Value, Vdf};
+use std::borrow::Cow;
+
+use crate::Error;
+
+const TYPE_NONE: u8 = 0;
+const TYPE_STRING: u8 = 1;
+const TYPE_INT32: u8 = 2;
+const TYPE_FLOAT32: u8 = 3;
+const TYPE_POINTER: u8 = 4;
+const TYPE_COLOR: u8 = 6;
+const TYPE_UINT64: u8 = 7;
+const TYPE_END: u8 = 8;
+const TYPE_INT64: u8 = 10;
+const TYPE_ALTERNATE_END: u8 = 11;
+
+/// Parses binary KeyValues into the same tree the text parser produces, with
+/// numbers rendered as strings.
+pub fn parse(mut data: &[u8]) -> Result<Vdf<'static>, Error> {
+    let r#type = get_u8(&mut data)?;
+    if r#type != TYPE_NONE {
+        return Err(Error::InvalidVDF(format!(
+            "expecting root object, got type {type}"
+        )));
+    }
+    let key = get_string(&mut data)?;
+    Ok(Vdf::new(Cow::Owned(key), Value::Obj(parse_obj(&mut data)?)))
+}
+
+fn parse_obj(data: &mut &[u8]) -> Result<Obj<'static>, Error> {
+    let mut obj = Obj::new();
+    loop {
+        let r#type = get_u8(data)?;
+        if r#type == TYPE_END || r#type == TYPE_ALTERNATE_END {
+            return Ok(obj);
+        }
+
+        let key = get_string(data)?;
+        let value = match r#type {
+            TYPE_NONE => Value::Obj(parse_obj(data)?),
+            TYPE_STRING => Value::Str(Cow::Owned(get_string(data)?)),
+            TYPE_INT32 | TYPE_POINTER | TYPE_COLOR => {
+                ensure(data, 4)?;
+                Value::Str(Cow::Owned(data.get_i32_le().to_string()))
+            }
+            TYPE_FLOAT32 => {
+                ensure(data, 4)?;
+                Value::Str(Cow::Owned(data.get_f32_le().to_string()))
+            }
+            TYPE_UINT64 => {
+                ensure(data, 8)?;
+                Value::Str(Cow::Owned(data.get_u64_le().to_string()))
+            }
+            TYPE_INT64 => {
+                ensure(data, 8)?;
+                Value::Str(Cow::Owned(data.get_i64_le().to_string()))
+            }
+            _ => return Err(Error::InvalidVDF(format!("unsupported binary type {type}"))),
+        };
+        obj.entry(Cow::Owned(key)).or_default().push(value);
+    }
+}
+
+fn ensure(data: &[u8], len: usize) -> Result<(), Error> {
+    if data.remaining() < len {
+        return Err(Error::Eof("binary vdf value".to_string()));
+    }
+    Ok(())
+}
+
+fn get_u8(data: &mut &[u8]) -> Result<u8, Error> {
+    ensure(data, 1)?;
+    Ok(data.get_u8())
+}
+
+fn get_string(data: &mut &[u8]) -> Result<String, Error> {
+    let end = data
+        .iter()
+        .position(|b| *b == 0)
+        .ok_or(Error::Eof("unterminated binary vdf string".to_string()))?;
+    let string = String::from_utf8_lossy(&data[..end]).into_owned();
+    data.advance(end + 1);
+    Ok(string)
+}
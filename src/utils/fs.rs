@@ -0,0 +1,49 @@
+use std::{
+    path::Path,
+    sync::atomic::{AtomicU64, Ordering},
+};
+use tokio::fs;
+
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// Writes through a temporary file renamed into place, so readers never see a partial
+/// file. Temporary names are unique per call, so concurrent writers don't collide.
+pub async fn write_atomic(path: &Path, content: impl AsRef<[u8]>) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).await?;
+    }
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp, content).await?;
+    if let Err(err) = fs::rename(&temp, path).await {
+        let _ = fs::remove_file(&temp).await;
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn concurrent_writes_to_one_path() {
+        let dir = std::env::temp_dir().join(format!("steam-cdn-atomic-{}", std::process::id()));
+        let path = dir.join("file");
+        let writes = (0..8u8).map(|i| {
+            let path = path.clone();
+            tokio::spawn(async move { write_atomic(&path, [i; 64]).await })
+        });
+        for write in futures::future::join_all(writes).await {
+            write.unwrap().unwrap();
+        }
+        let content = fs::read(&path).await.unwrap();
+        fs::remove_dir_all(&dir).await.ok();
+        assert_eq!(content.len(), 64);
+        assert!(content.iter().all(|byte| *byte == content[0]));
+    }
+}
@@ -0,0 +1,27 @@
+use std::fmt::Write;
+
+use crate::Error;
+
+pub fn encode<T: AsRef<[u8]>>(input: T) -> String {
+    input.as_ref().iter().fold(String::new(), |mut output, b| {
+        let _ = write!(output, "{b:02x}");
+        output
+    })
+}
+
+pub fn decode<T: AsRef<str>>(input: T) -> Result<Vec<u8>, Error> {
+    let input = input.as_ref();
+    if input.len() % 2 != 0 {
+        return Err(Error::Unexpected("odd length hex string".to_string()));
+    }
+
+    (0..input.len())
+        .step_by(2)
+        .map(|i| {
+            input
+                .get(i..i + 2)
+                .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                .ok_or(Error::Unexpected(format!("invalid hex string: {input}")))
+        })
+        .collect()
+}
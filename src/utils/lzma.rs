@@ -1,5 +1,9 @@
-use lzma_rs::decompress::raw::{LzmaDecoder, LzmaParams, LzmaProperties};
-use std::io::{Cursor, SeekFrom};
+use lzma_rs::{
+    compress::{Options, UnpackedSize},
+    decompress::raw::{LzmaDecoder, LzmaParams, LzmaProperties},
+    lzma_compress_with_options,
+};
+use std::io::{BufReader, Cursor, SeekFrom};
 use tokio::{
     io::{AsyncReadExt, AsyncSeekExt},
     task,
@@ -77,3 +81,73 @@ pub async fn decompress(data: &[u8]) -> Result<Vec<u8>, Error> {
 
     Ok(decompressed_data)
 }
+
+/// Wraps `data` in a `VZa` container that `decompress` reads back. lzma-rs only
+/// encodes literals, so the result is valid but barely smaller than the input.
+pub async fn compress(data: &[u8]) -> Result<Vec<u8>, Error> {
+    let size = u32::try_from(data.len())
+        .map_err(|_| Error::Unexpected("too large for a VZ container".to_string()))?;
+    let data = data.to_vec();
+    task::spawn_blocking(move || -> Result<Vec<u8>, Error> {
+        let crc32 = crc32fast::hash(&data);
+
+        // without the size in the header the stream is the 5 property bytes
+        // followed by the payload, exactly what goes between header and footer
+        let mut stream = Vec::with_capacity(data.len() + VZ_HEADER_LENGTH + VZ_FOOTER_LENGTH);
+        stream.extend_from_slice(&VZ_HEADER.to_le_bytes());
+        stream.push(VZ_VERSION as u8);
+        stream.extend_from_slice(&crc32.to_le_bytes());
+        lzma_compress_with_options(
+            &mut BufReader::new(&data[..]),
+            &mut stream,
+            &Options {
+                unpacked_size: UnpackedSize::SkipWritingToHeader,
+            },
+        )?;
+        stream.extend_from_slice(&crc32.to_le_bytes());
+        stream.extend_from_slice(&size.to_le_bytes());
+        stream.extend_from_slice(&VZ_FOOTER.to_le_bytes());
+        Ok(stream)
+    })
+    .await?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn round_trip(data: &[u8]) {
+        let compressed = compress(data).await.unwrap();
+        assert!(is_vz(&compressed));
+        assert_eq!(decompress(&compressed).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn round_trips_empty_input() {
+        round_trip(&[]).await;
+    }
+
+    #[tokio::test]
+    async fn round_trips_small_input() {
+        round_trip(b"steam content").await;
+    }
+
+    #[tokio::test]
+    async fn round_trips_a_full_chunk() {
+        let data = (0..1024 * 1024)
+            .map(|i: u32| (i.wrapping_mul(2_654_435_761) >> 13) as u8)
+            .collect::<Vec<u8>>();
+        round_trip(&data).await;
+    }
+
+    #[tokio::test]
+    async fn rejects_a_crc_mismatch() {
+        let mut compressed = compress(b"steam content").await.unwrap();
+        let footer = compressed.len() - VZ_FOOTER_LENGTH;
+        compressed[footer] ^= 0xff;
+        assert!(matches!(
+            decompress(&compressed).await,
+            Err(Error::Decompress(_))
+        ));
+    }
+}
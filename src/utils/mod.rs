@@ -1,2 +1,8 @@
+pub mod adler;
 pub mod base64;
+pub mod binary_vdf;
+pub mod fs;
+pub mod hex;
 pub mod lzma;
+pub mod vdf;
+pub mod zip;
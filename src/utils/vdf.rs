@@ -0,0 +1,25 @@
+use keyvalues_parser::{Obj, Value};
+
+pub fn get_str<'a>(obj: &'a Obj<'_>, key: &str) -> Option<&'a str> {
+    obj.get(key)
+        .and_then(|values| values.first())
+        .and_then(Value::get_str)
+}
+
+pub fn get_obj<'a>(obj: &'a Obj<'_>, key: &str) -> Option<&'a Obj<'a>> {
+    obj.get(key)
+        .and_then(|values| values.first())
+        .and_then(Value::get_obj)
+}
+
+pub fn get_list(obj: &Obj<'_>, key: &str) -> Vec<String> {
+    get_str(obj, key)
+        .map(|list| {
+            list.split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
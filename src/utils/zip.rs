@@ -0,0 +1,16 @@
+use std::io::{Cursor, Write};
+use zip::{write::SimpleFileOptions, CompressionMethod, ZipWriter};
+
+use crate::Error;
+
+/// Deflates `data` into an archive holding it as its only entry, the layout
+/// chunks and manifests are read back from.
+pub fn compress(name: &str, data: &[u8]) -> Result<Vec<u8>, Error> {
+    let mut writer = ZipWriter::new(Cursor::new(Vec::new()));
+    writer.start_file(
+        name,
+        SimpleFileOptions::default().compression_method(CompressionMethod::Deflated),
+    )?;
+    writer.write_all(data)?;
+    Ok(writer.finish()?.into_inner())
+}
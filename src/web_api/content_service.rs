@@ -27,8 +27,7 @@ struct ContentServerDirectory {
     pub response: ContentServerDirectoryInner,
 }
 
-#[allow(dead_code)]
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CDNServer {
     pub r#type: String,
     pub https: bool,
@@ -38,11 +37,32 @@ pub struct CDNServer {
     pub cell_id: u32,
     pub load: u32,
     pub weighted_load: u32,
+    pub priority_class: u32,
+}
+
+impl CDNServer {
+    pub fn new<H: Into<String>>(host: H, port: u16, https: bool) -> Self {
+        let host = host.into();
+        Self {
+            r#type: "CDN".to_string(),
+            https,
+            vhost: host.clone(),
+            host,
+            port,
+            cell_id: 0,
+            load: 0,
+            weighted_load: 0,
+            priority_class: 0,
+        }
+    }
 }
 
-pub async fn get_servers_for_steam_pipe(cell_id: u32) -> Result<Vec<CDNServer>, Error> {
+pub async fn get_servers_for_steam_pipe(
+    client: &Client,
+    cell_id: u32,
+) -> Result<Vec<CDNServer>, Error> {
     Ok(
-        Client::new()
+        client
             .get("https://api.steampowered.com/IContentServerDirectoryService/GetServersForSteamPipe/v1/")
             .query(&[("cell_id", cell_id)])
             .send().await?
@@ -61,6 +81,7 @@ pub async fn get_servers_for_steam_pipe(cell_id: u32) -> Result<Vec<CDNServer>,
                     cell_id: server.cell_id.unwrap_or(0),
                     load: server.load,
                     weighted_load: server.weighted_load,
+                    priority_class: server.priority_class,
                 }
             })
             .collect::<Vec<CDNServer>>()
@@ -0,0 +1,50 @@
+#![cfg(unix)]
+
+use std::sync::Arc;
+
+use steam_cdn::{CDNClient, DepotBuilder, FakeConnection};
+
+const DEPOT_KEY: [u8; 32] = [7; 32];
+
+#[tokio::test]
+async fn symlinks_are_kept_as_links() {
+    let dir = std::env::temp_dir().join(format!("steam-cdn-symlinks-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("sub")).unwrap();
+    std::fs::write(dir.join("sub").join("file.txt"), b"content").unwrap();
+    // pointing at an ancestor, which following would recurse into forever
+    std::os::unix::fs::symlink("..", dir.join("sub").join("up")).unwrap();
+    std::os::unix::fs::symlink("sub/file.txt", dir.join("link.txt")).unwrap();
+
+    let depot = DepotBuilder::new(11, DEPOT_KEY).build(&dir).await;
+    std::fs::remove_dir_all(&dir).ok();
+    let depot = depot.unwrap();
+
+    let client = CDNClient::new(Arc::new(FakeConnection::default()))
+        .await
+        .unwrap();
+    let manifest = client
+        .parse_manifest(&depot.manifest, Some(DEPOT_KEY))
+        .await
+        .unwrap();
+    let files = manifest
+        .files()
+        .iter()
+        .map(|file| {
+            (
+                file.full_path(),
+                file.linktarget(),
+                file.size(),
+                file.flags(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        files,
+        [
+            ("link.txt".to_string(), "sub/file.txt".to_string(), 0, 512),
+            ("sub".to_string(), String::new(), 0, 64),
+            ("sub/file.txt".to_string(), String::new(), 7, 0),
+            ("sub/up".to_string(), "..".to_string(), 0, 512),
+        ]
+    );
+}
@@ -0,0 +1,107 @@
+use std::sync::Arc;
+
+use steam_cdn::{CDNClient, EResult, FakeCall, FakeConnection, License};
+
+const APP_ID: u32 = 10;
+const DEPOT_ID: u32 = 11;
+
+async fn client(connection: &Arc<FakeConnection>) -> CDNClient {
+    CDNClient::new(connection.clone()).await.unwrap()
+}
+
+#[tokio::test]
+async fn product_info() {
+    let connection = Arc::new(FakeConnection::default());
+    connection.add_app(
+        APP_ID,
+        r#""appinfo" { "common" { "name" "Fake App" "type" "Game" } }"#,
+    );
+    let client = client(&connection).await;
+
+    let apps = client.get_app_info(vec![APP_ID]).await.unwrap();
+    assert_eq!(apps.len(), 1);
+    assert_eq!(apps[0].app_id, APP_ID);
+    assert_eq!(apps[0].common.name.as_deref(), Some("Fake App"));
+    assert_eq!(apps[0].common.r#type.as_deref(), Some("game"));
+
+    assert!(client
+        .get_app_info(vec![APP_ID + 1])
+        .await
+        .unwrap()
+        .is_empty());
+
+    connection.fail(FakeCall::ProductInfo, Some(EResult::Busy));
+    let err = client.get_app_info(vec![APP_ID]).await.unwrap_err();
+    assert!(matches!(err.eresult(), Some(EResult::Busy)));
+}
+
+#[tokio::test]
+async fn depot_key() {
+    let connection = Arc::new(FakeConnection::default());
+    connection.add_depot_key(DEPOT_ID, [7; 32]);
+    let client = client(&connection).await;
+
+    let key = client
+        .get_depot_decryption_key(APP_ID, DEPOT_ID)
+        .await
+        .unwrap();
+    assert_eq!(key, Some([7; 32]));
+    assert_eq!(client.session_depot_keys().get(&DEPOT_ID), Some(&[7; 32]));
+
+    assert!(client
+        .get_depot_decryption_key(APP_ID, DEPOT_ID + 1)
+        .await
+        .is_err());
+}
+
+#[tokio::test]
+async fn request_code_is_cached() {
+    let connection = Arc::new(FakeConnection::default());
+    connection.add_request_code(99, 1234);
+    let client = client(&connection).await;
+
+    for _ in 0..2 {
+        let code = client
+            .get_manifest_request_code(APP_ID, DEPOT_ID, 99)
+            .await
+            .unwrap();
+        assert_eq!(code, 1234);
+    }
+    assert_eq!(connection.calls(FakeCall::ManifestRequestCode), 1);
+
+    // another branch is another code
+    assert!(client
+        .get_branch_manifest_request_code(APP_ID, DEPOT_ID, 99, Some("beta"), None)
+        .await
+        .is_ok());
+    assert_eq!(connection.calls(FakeCall::ManifestRequestCode), 2);
+}
+
+fn license(package_id: u32) -> License {
+    License {
+        package_id,
+        license_type: 0,
+        flags: 0,
+        time_created: 0,
+        owner_id: 0,
+    }
+}
+
+#[tokio::test]
+async fn supplied_licenses_until_the_next_push() {
+    let connection = Arc::new(FakeConnection::default());
+    let client = CDNClient::builder(connection.clone())
+        .licenses(vec![license(1)])
+        .build()
+        .await
+        .unwrap();
+
+    let licenses = client.get_licenses().await.unwrap();
+    assert_eq!(licenses.len(), 1);
+    assert_eq!(licenses[0].package_id, 1);
+
+    connection.set_licenses(vec![license(2), license(3)]);
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    let licenses = client.get_licenses().await.unwrap();
+    assert_eq!(licenses.len(), 2);
+}
@@ -0,0 +1,279 @@
+use std::{path::PathBuf, sync::Arc, time::Duration};
+
+use steam_cdn::{
+    test_support::mock_cdn::{Fault, MockCdn},
+    BuiltDepot, CDNClient, DepotBuilder, Error, FakeConnection, HttpOptions, RetryPolicy,
+    StaticServerSource,
+};
+
+const DEPOT_ID: u32 = 11;
+const DEPOT_KEY: [u8; 32] = [7; 32];
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A directory with a file spanning a few chunks, removed when dropped.
+struct Content {
+    dir: PathBuf,
+    data: Vec<u8>,
+}
+
+impl Content {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("steam-cdn-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        // pseudo random so chunks neither compress away nor repeat
+        let mut seed = 0x2545_f491_u32;
+        let data = (0..3 * CHUNK_SIZE + 1000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect::<Vec<u8>>();
+        std::fs::write(dir.join("data.bin"), &data).unwrap();
+        Self { dir, data }
+    }
+
+    async fn build(&self) -> BuiltDepot {
+        DepotBuilder::new(DEPOT_ID, DEPOT_KEY)
+            .chunk_size(CHUNK_SIZE)
+            .build(&self.dir)
+            .await
+            .unwrap()
+    }
+}
+
+impl Drop for Content {
+    fn drop(&mut self) {
+        std::fs::remove_dir_all(&self.dir).ok();
+    }
+}
+
+async fn client(mocks: &[&MockCdn], read_timeout: Option<Duration>) -> CDNClient {
+    CDNClient::builder(Arc::new(FakeConnection::default()))
+        .server_source(StaticServerSource::new(
+            mocks.iter().map(|mock| mock.server()).collect(),
+        ))
+        .http_options(HttpOptions {
+            read_timeout,
+            ..Default::default()
+        })
+        .retry_policy(RetryPolicy {
+            max_attempts: 3,
+            backoff: Duration::from_millis(10),
+        })
+        .build()
+        .await
+        .unwrap()
+}
+
+/// Fetches the manifest, then applies `fault` to the first chunk request only.
+async fn download_with_fault(
+    client: &CDNClient,
+    mock: &MockCdn,
+    depot: &BuiltDepot,
+    fault: Fault,
+) -> Vec<u8> {
+    let manifest = client
+        .get_manifest(DEPOT_ID, depot.manifest_gid, Some(1), Some(DEPOT_KEY))
+        .await
+        .unwrap();
+    let file = manifest
+        .files()
+        .iter()
+        .find(|file| file.filename() == "data.bin")
+        .unwrap();
+    mock.push_fault(fault);
+    let mut downloaded = Vec::new();
+    file.download(DEPOT_KEY, &mut downloaded, Some(1))
+        .await
+        .unwrap();
+    downloaded
+}
+
+async fn assert_recovers(name: &str, fault: Fault, read_timeout: Option<Duration>) {
+    let content = Content::new(name);
+    let depot = content.build().await;
+    let mock = MockCdn::start().await.unwrap();
+    mock.add_depot(&depot);
+    let client = client(&[&mock], read_timeout).await;
+
+    let downloaded = download_with_fault(&client, &mock, &depot, fault).await;
+    assert_eq!(downloaded, content.data);
+    let chunk_requests = mock
+        .requests()
+        .iter()
+        .filter(|path| path.contains("/chunk/"))
+        .count();
+    assert_eq!(chunk_requests, depot.chunks.len() + 1);
+}
+
+#[tokio::test]
+async fn server_error_is_retried() {
+    assert_recovers("status", Fault::Status(503), None).await;
+}
+
+#[tokio::test]
+async fn corrupt_chunk_is_retried() {
+    assert_recovers("corrupt", Fault::Corrupt, None).await;
+}
+
+#[tokio::test]
+async fn truncated_chunk_is_retried() {
+    assert_recovers("truncate", Fault::Truncate, None).await;
+}
+
+#[tokio::test]
+async fn slow_chunk_times_out_and_is_retried() {
+    assert_recovers(
+        "delay",
+        Fault::Delay(Duration::from_secs(30)),
+        Some(Duration::from_millis(200)),
+    )
+    .await;
+}
+
+#[tokio::test]
+async fn missing_chunk_is_not_retried() {
+    let content = Content::new("missing");
+    let depot = content.build().await;
+    let mock = MockCdn::start().await.unwrap();
+    mock.add_depot(&depot);
+    let client = client(&[&mock], None).await;
+
+    let manifest = client
+        .get_manifest(DEPOT_ID, depot.manifest_gid, Some(1), Some(DEPOT_KEY))
+        .await
+        .unwrap();
+    mock.set_fault(Some(Fault::Status(404)));
+    let mut downloaded = Vec::new();
+    assert!(manifest.files()[0]
+        .download(DEPOT_KEY, &mut downloaded, Some(1))
+        .await
+        .is_err());
+    assert_eq!(
+        mock.requests()
+            .iter()
+            .filter(|path| path.contains("/chunk/"))
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn failing_server_is_avoided() {
+    let content = Content::new("failover");
+    let depot = content.build().await;
+    let failing = MockCdn::start().await.unwrap();
+    let healthy = MockCdn::start().await.unwrap();
+    failing.add_depot(&depot);
+    healthy.add_depot(&depot);
+    failing.set_fault(Some(Fault::Status(503)));
+    let client = client(&[&failing, &healthy], None).await;
+
+    let downloaded = download_with_fault(&client, &healthy, &depot, Fault::Corrupt).await;
+    assert_eq!(downloaded, content.data);
+    // once it has failed, chunks stop going there
+    let failed_chunk_requests = failing
+        .requests()
+        .iter()
+        .filter(|path| path.contains("/chunk/"))
+        .count();
+    assert!(failed_chunk_requests <= 1);
+}
+
+#[tokio::test]
+async fn wrong_key_fails_without_retries() {
+    let content = Content::new("wrong-key");
+    let depot = DepotBuilder::new(DEPOT_ID, DEPOT_KEY)
+        .chunk_size(CHUNK_SIZE)
+        .encrypt_filenames(false)
+        .build(&content.dir)
+        .await
+        .unwrap();
+    let mock = MockCdn::start().await.unwrap();
+    mock.add_depot(&depot);
+    let client = client(&[&mock], None).await;
+
+    let manifest = client
+        .get_manifest(DEPOT_ID, depot.manifest_gid, Some(1), None)
+        .await
+        .unwrap();
+    let mut downloaded = Vec::new();
+    let result = manifest.files()[0]
+        .download([9; 32], &mut downloaded, Some(1))
+        .await;
+    assert!(matches!(result, Err(Error::WrongDepotKey)));
+    // the sample chunk, fetched a second time in case it was corrupted
+    let chunk_requests = mock
+        .requests()
+        .iter()
+        .filter(|path| path.contains("/chunk/"))
+        .count();
+    assert_eq!(chunk_requests, 2);
+
+    manifest.files()[0]
+        .download(DEPOT_KEY, &mut downloaded, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(downloaded, content.data);
+}
+
+#[tokio::test]
+async fn unusable_cache_dir_is_a_miss() {
+    let content = Content::new("cache");
+    let depot = content.build().await;
+    let mock = MockCdn::start().await.unwrap();
+    mock.add_depot(&depot);
+    // a regular file where the cache directory's parent should be
+    let blocker = content.dir.join("blocker");
+    std::fs::write(&blocker, b"").unwrap();
+    let client = CDNClient::builder(Arc::new(FakeConnection::default()))
+        .server_source(StaticServerSource::new(vec![mock.server()]))
+        .cache_dir(blocker.join("cache"))
+        .build()
+        .await
+        .unwrap();
+
+    let manifest = client
+        .get_manifest(DEPOT_ID, depot.manifest_gid, Some(1), Some(DEPOT_KEY))
+        .await
+        .unwrap();
+    assert_eq!(manifest.manifest_gid(), depot.manifest_gid);
+}
+
+#[tokio::test]
+async fn slow_server_loses_its_share_to_hedges() {
+    let content = Content::new("hedge");
+    let depot = DepotBuilder::new(DEPOT_ID, DEPOT_KEY)
+        .chunk_size(1024)
+        .build(&content.dir)
+        .await
+        .unwrap();
+    let slow = MockCdn::start().await.unwrap();
+    let fast = MockCdn::start().await.unwrap();
+    slow.add_depot(&depot);
+    fast.add_depot(&depot);
+    let client = client(&[&slow, &fast], None).await;
+    let manifest = client
+        .get_manifest(DEPOT_ID, depot.manifest_gid, Some(1), Some(DEPOT_KEY))
+        .await
+        .unwrap();
+    let file = &manifest.files()[0];
+
+    // enough quick chunks for the client to know what slow looks like
+    let mut downloaded = Vec::new();
+    file.download(DEPOT_KEY, &mut downloaded, Some(1))
+        .await
+        .unwrap();
+    let warm_up = slow.requests().len();
+
+    slow.set_latency(Duration::from_secs(5));
+    let mut downloaded = Vec::new();
+    file.download(DEPOT_KEY, &mut downloaded, Some(1))
+        .await
+        .unwrap();
+    assert_eq!(downloaded, content.data);
+    // without the hedges counting against it, the slow server keeps most of the chunks
+    assert!(slow.requests().len() - warm_up < 20);
+}